@@ -0,0 +1,417 @@
+use std::fmt::Debug;
+
+use crate::{Result, Error, Button, DipSwitches};
+use crate::emulator::{Event, Sound};
+
+macro_rules! check_sound_events {
+    ( $events:expr, $last_port:expr, $val:expr, $(($msk:expr,$snd:expr)),* ) => {
+        $(
+            if $val & $msk != 0 && $last_port & $msk == 0 {
+                $events.push(Event::PlaySound($snd));
+            } else if $val & $msk == 0 && $last_port & $msk != 0 {
+                $events.push(Event::StopSound($snd));
+            }
+        )*
+    };
+}
+
+/// Buttons currently held down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Controls {
+    buttons: u16,
+}
+
+impl Controls {
+    pub fn pressed(&self, button: Button) -> bool {
+        self.buttons & (1 << button.index()) != 0
+    }
+
+    pub(crate) fn set(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.buttons |= 1 << button.index();
+        } else {
+            self.buttons &= !(1 << button.index());
+        }
+    }
+
+    pub(crate) fn bits(&self) -> u16 {
+        self.buttons
+    }
+
+    pub(crate) fn from_bits(bits: u16) -> Self {
+        Self { buttons: bits }
+    }
+
+    /// Combines `mask` for each pressed button.
+    fn mask(&self, buttons: &[(Button, u8)]) -> u8 {
+        buttons.iter().filter(|(button, _)| self.pressed(*button)).fold(0, |bits, (_, mask)| bits | mask)
+    }
+}
+
+/// Ports wired to the `ShiftRegister`, which every Midway 8080 board has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShiftPorts {
+    pub amount: u8,
+    pub data: u8,
+    pub result: u8,
+}
+
+/// Everything on a Midway 8080 board besides the CPU, memory, video and shift register:
+/// how the controls, switches and sound circuits are wired to the I/O ports.
+pub trait IoBoard: Debug + Send {
+    fn shift_ports(&self) -> ShiftPorts;
+
+    /// Returns `None` for ports the board doesn't decode.
+    fn read(&mut self, port: u8, controls: Controls, dip_switches: DipSwitches) -> Option<u8>;
+
+    /// Returns `false` for ports the board doesn't decode.
+    fn write(&mut self, port: u8, val: u8, events: &mut Vec<Event>) -> bool;
+
+    fn reset(&mut self);
+
+    fn save_state(&self) -> Vec<u8>;
+
+    fn load_state(&mut self, data: &[u8]) -> Result<()>;
+
+    fn clone_box(&self) -> Box<dyn IoBoard>;
+}
+
+impl Clone for Box<dyn IoBoard> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// The I/O boards this crate emulates, named so machine profiles can be constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Board {
+    Invaders,
+    InvadersII,
+    Taito,
+    BootHill,
+    GunFight,
+}
+
+impl Board {
+    pub fn create(self) -> Box<dyn IoBoard> {
+        match self {
+            Self::Invaders => Box::new(InvadersBoard::default()),
+            Self::InvadersII => Box::new(InvadersIIBoard::default()),
+            Self::Taito => Box::new(TaitoBoard),
+            Self::BootHill => Box::new(DuelBoard::boot_hill()),
+            Self::GunFight => Box::new(DuelBoard::gun_fight()),
+        }
+    }
+}
+
+const INVADERS_SHIFT_PORTS: ShiftPorts = ShiftPorts { amount: 2, data: 4, result: 3 };
+
+/// Bits of input port 1. The coin switch is active low.
+const INVADERS_PORT_1: [(Button, u8); 6] = [
+    (Button::Coin, 0b0000_0001),
+    (Button::P2Start, 0b0000_0010),
+    (Button::P1Start, 0b0000_0100),
+    (Button::P1Shoot, 0b0001_0000),
+    (Button::P1Left, 0b0010_0000),
+    (Button::P1Right, 0b0100_0000),
+];
+
+/// Player 1's controls on port 0, which only the later Midway games read. Bits 1 to 3
+/// are pulled high.
+const INVADERS_PORT_0: [(Button, u8); 3] = [
+    (Button::P1Shoot, 0b0001_0000),
+    (Button::P1Left, 0b0010_0000),
+    (Button::P1Right, 0b0100_0000),
+];
+
+/// Bits of input port 2, besides the DIP switches.
+const INVADERS_PORT_2: [(Button, u8); 4] = [
+    (Button::Tilt, 0b0000_0100),
+    (Button::P2Shoot, 0b0001_0000),
+    (Button::P2Left, 0b0010_0000),
+    (Button::P2Right, 0b0100_0000),
+];
+
+fn invaders_input(port: u8, controls: Controls, dip_switches: DipSwitches) -> Option<u8> {
+    match port {
+        1 => Some(controls.mask(&INVADERS_PORT_1) ^ 0b0000_0001),
+        2 => Some(controls.mask(&INVADERS_PORT_2) | dip_switches.bits()),
+        _ => None,
+    }
+}
+
+/// Space Invaders and the sets running on its board, with the sound samples triggered
+/// from ports 3 and 5 and the watchdog on port 6 reported as `Event::Debug`.
+#[derive(Debug, Clone, Default)]
+pub struct InvadersBoard {
+    last_port_3: u8,
+    last_port_5: u8,
+}
+
+impl IoBoard for InvadersBoard {
+    fn shift_ports(&self) -> ShiftPorts {
+        INVADERS_SHIFT_PORTS
+    }
+
+    fn read(&mut self, port: u8, controls: Controls, dip_switches: DipSwitches) -> Option<u8> {
+        invaders_input(port, controls, dip_switches)
+    }
+
+    fn write(&mut self, port: u8, val: u8, events: &mut Vec<Event>) -> bool {
+        match port {
+            3 => {
+                check_sound_events!(events, self.last_port_3, val,
+                    (0x01, Sound::UFO),
+                    (0x02, Sound::Shoot),
+                    (0x04, Sound::PlayerDie),
+                    (0x08, Sound::InvaderDie)
+                );
+                self.last_port_3 = val;
+            }
+            5 => {
+                check_sound_events!(events, self.last_port_5, val,
+                    (0x01, Sound::Bomp1),
+                    (0x02, Sound::Bomp2),
+                    (0x04, Sound::Bomp3),
+                    (0x08, Sound::Bomp4),
+                    (0x10, Sound::UFOExplode)
+                );
+                self.last_port_5 = val;
+            }
+            6 => events.push(Event::Debug(val)),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.last_port_3, self.last_port_5]
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        let &[last_port_3, last_port_5] = data else {
+            return Err(Error::InvalidSaveState);
+        };
+
+        *self = Self { last_port_3, last_port_5 };
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn IoBoard> {
+        Box::new(self.clone())
+    }
+}
+
+/// Space Invaders Part II and Deluxe, which also read player 1's controls from port 0.
+/// The DIP switch Space Invaders uses for the bonus life selects their preset mode instead.
+#[derive(Debug, Clone, Default)]
+pub struct InvadersIIBoard {
+    invaders: InvadersBoard,
+}
+
+impl IoBoard for InvadersIIBoard {
+    fn shift_ports(&self) -> ShiftPorts {
+        INVADERS_SHIFT_PORTS
+    }
+
+    fn read(&mut self, port: u8, controls: Controls, dip_switches: DipSwitches) -> Option<u8> {
+        match port {
+            0 => Some(controls.mask(&INVADERS_PORT_0) | 0b0000_1110),
+            _ => self.invaders.read(port, controls, dip_switches),
+        }
+    }
+
+    fn write(&mut self, port: u8, val: u8, events: &mut Vec<Event>) -> bool {
+        self.invaders.write(port, val, events)
+    }
+
+    fn reset(&mut self) {
+        self.invaders.reset();
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.invaders.save_state()
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        self.invaders.load_state(data)
+    }
+
+    fn clone_box(&self) -> Box<dyn IoBoard> {
+        Box::new(self.clone())
+    }
+}
+
+/// Taito's boards for Lunar Rescue and Balloon Bomber, wired like Space Invaders' for
+/// the controls and shift register. Their sound circuits aren't emulated, so writes to
+/// the sound ports are accepted and ignored.
+#[derive(Debug, Clone, Default)]
+pub struct TaitoBoard;
+
+impl IoBoard for TaitoBoard {
+    fn shift_ports(&self) -> ShiftPorts {
+        INVADERS_SHIFT_PORTS
+    }
+
+    fn read(&mut self, port: u8, controls: Controls, dip_switches: DipSwitches) -> Option<u8> {
+        invaders_input(port, controls, dip_switches)
+    }
+
+    fn write(&mut self, port: u8, _val: u8, _events: &mut Vec<Event>) -> bool {
+        matches!(port, 3 | 5 | 6)
+    }
+
+    fn reset(&mut self) {}
+
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        if !data.is_empty() {
+            return Err(Error::InvalidSaveState);
+        }
+
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn IoBoard> {
+        Box::new(self.clone())
+    }
+}
+
+/// Player controls on ports 0 and 1 of the two-player western duels, all active low.
+/// The guns' aim isn't emulated and stays level.
+const DUEL_CONTROLS: [[(Button, u8); 3]; 2] = [
+    [(Button::P1Left, 0b0000_0100), (Button::P1Right, 0b0000_1000), (Button::P1Shoot, 0b1000_0000)],
+    [(Button::P2Left, 0b0000_0100), (Button::P2Right, 0b0000_1000), (Button::P2Shoot, 0b1000_0000)],
+];
+
+/// Coin and start on port 2, active low.
+const DUEL_PORT_2: [(Button, u8); 2] = [(Button::P1Start, 0b0100_0000), (Button::Coin, 0b1000_0000)];
+
+/// Boot Hill and Gun Fight, which share their controls but not their port wiring. Their
+/// sound circuits and watchdog are accepted and ignored.
+#[derive(Debug, Clone)]
+pub struct DuelBoard {
+    shift_ports: ShiftPorts,
+    outputs: &'static [u8],
+}
+
+impl DuelBoard {
+    pub fn boot_hill() -> Self {
+        Self { shift_ports: ShiftPorts { amount: 1, data: 2, result: 3 }, outputs: &[3, 4, 5, 6] }
+    }
+
+    pub fn gun_fight() -> Self {
+        Self { shift_ports: ShiftPorts { amount: 2, data: 4, result: 3 }, outputs: &[1] }
+    }
+}
+
+impl IoBoard for DuelBoard {
+    fn shift_ports(&self) -> ShiftPorts {
+        self.shift_ports
+    }
+
+    fn read(&mut self, port: u8, controls: Controls, _dip_switches: DipSwitches) -> Option<u8> {
+        match port {
+            0 | 1 => Some(!controls.mask(&DUEL_CONTROLS[port as usize])),
+            2 => Some(!controls.mask(&DUEL_PORT_2)),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, port: u8, _val: u8, _events: &mut Vec<Event>) -> bool {
+        self.outputs.contains(&port)
+    }
+
+    fn reset(&mut self) {}
+
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        if !data.is_empty() {
+            return Err(Error::InvalidSaveState);
+        }
+
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn IoBoard> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_invaders_inputs() {
+        let mut board = InvadersBoard::default();
+        let mut controls = Controls::default();
+        assert_eq!(board.read(1, controls, DipSwitches::default()), Some(0b0000_0001));
+
+        controls.set(Button::Coin, true);
+        controls.set(Button::P1Left, true);
+        controls.set(Button::P2Shoot, true);
+        assert_eq!(board.read(1, controls, DipSwitches::default()), Some(0b0010_0000));
+        assert_eq!(board.read(2, controls, DipSwitches { lives: 4, ..Default::default() }), Some(0b0001_0001));
+        assert_eq!(board.read(0, controls, DipSwitches::default()), None);
+    }
+
+    #[test]
+    fn test_invaders_ii_inputs() {
+        let mut board = InvadersIIBoard::default();
+        let mut controls = Controls::default();
+        assert_eq!(board.read(0, controls, DipSwitches::default()), Some(0b0000_1110));
+
+        controls.set(Button::P1Shoot, true);
+        controls.set(Button::P1Right, true);
+        assert_eq!(board.read(0, controls, DipSwitches::default()), Some(0b0101_1110));
+        assert_eq!(board.read(1, controls, DipSwitches::default()), Some(0b0101_0001));
+        assert_eq!(board.read(2, controls, DipSwitches { bonus_life_at_1000: true, ..Default::default() }), Some(0b0000_1000));
+
+        let mut events = Vec::new();
+        assert!(board.write(5, 0x10, &mut events));
+        assert_eq!(events, [Event::PlaySound(Sound::UFOExplode)]);
+        assert_eq!(InvadersBoard::default().read(0, controls, DipSwitches::default()), None);
+    }
+
+    #[test]
+    fn test_duel_inputs() {
+        let mut board = DuelBoard::gun_fight();
+        let mut controls = Controls::default();
+        controls.set(Button::P2Left, true);
+        controls.set(Button::Coin, true);
+        assert_eq!(board.read(0, controls, DipSwitches::default()), Some(0xFF));
+        assert_eq!(board.read(1, controls, DipSwitches::default()), Some(0b1111_1011));
+        assert_eq!(board.read(2, controls, DipSwitches::default()), Some(0b0111_1111));
+        assert_eq!(board.shift_ports().amount, 2);
+        assert_eq!(DuelBoard::boot_hill().shift_ports().amount, 1);
+    }
+
+    #[test]
+    fn test_board_state() {
+        let mut board = InvadersBoard::default();
+        let mut events = Vec::new();
+        assert!(board.write(3, 0x03, &mut events));
+        assert!(!board.write(7, 0x00, &mut events));
+        assert_eq!(events, [Event::PlaySound(Sound::UFO), Event::PlaySound(Sound::Shoot)]);
+
+        let mut restored = InvadersBoard::default();
+        restored.load_state(&board.save_state()).unwrap();
+        events.clear();
+        restored.write(3, 0x01, &mut events);
+        assert_eq!(events, [Event::StopSound(Sound::Shoot)]);
+
+        assert!(matches!(restored.load_state(&[0]), Err(Error::InvalidSaveState)));
+    }
+}
@@ -0,0 +1,36 @@
+use crate::{Result, Memory};
+
+/// Everything the CPU is wired to: the memory map and the I/O ports. The CPU calls these
+/// while executing an instruction, so a value read from a port is available to the very
+/// next instruction.
+pub trait Bus {
+    fn read(&self, adr: u16) -> u8;
+
+    fn write(&mut self, adr: u16, val: u8);
+
+    fn input(&mut self, port: u8) -> Result<u8>;
+
+    fn output(&mut self, port: u8, val: u8) -> Result<()>;
+}
+
+/// Bare memory with nothing on the ports: inputs read as 0 and outputs are ignored.
+/// Writes to ROM are ignored, as on the real hardware.
+impl Bus for Memory {
+    fn read(&self, adr: u16) -> u8 {
+        self[adr]
+    }
+
+    fn write(&mut self, adr: u16, val: u8) {
+        if !self.is_rom(adr) {
+            self[adr] = val;
+        }
+    }
+
+    fn input(&mut self, _port: u8) -> Result<u8> {
+        Ok(0)
+    }
+
+    fn output(&mut self, _port: u8, _val: u8) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{Result, Error, CPU, CPUEvent, Memory, Register};
+
+const BDOS: u16 = 0x0005;
+const TPA: u16 = 0x0100;
+const TPA_TOP: u16 = 0xFE00;
+
+/// Minimal CP/M environment for running 8080 diagnostic `.COM` programs.
+///
+/// The program is loaded at 0x0100 on a flat 64K memory map. `CALL 5` is
+/// trapped to emulate BDOS console output (functions 2 and 9), and a jump
+/// to 0x0000 (warm boot) ends the run. A program still running after the
+/// step budget passed to `run` fails with `Error::StepLimitExceeded`.
+#[derive(Debug, Clone)]
+pub struct CpmMachine {
+    cpu: CPU,
+    output: String,
+    cycles: u64,
+}
+
+impl CpmMachine {
+    pub fn new(program: &[u8]) -> Self {
+        let mut memory = Memory::flat();
+        memory.load(TPA, program);
+
+        memory[0x0000] = 0x76;                  // HLT, in case warm boot is ever executed
+        memory[BDOS] = 0xC9;                    // RET, so trapped calls return normally
+        memory[0x0006] = (TPA_TOP & 0xFF) as u8;
+        memory[0x0007] = (TPA_TOP >> 8) as u8;
+
+        let mut cpu = CPU::with_bus(memory);
+        cpu.set_pc(TPA);
+
+        Self {
+            cpu,
+            output: String::new(),
+            cycles: 0,
+        }
+    }
+
+    pub fn run(&mut self, max_steps: u64) -> Result<()> {
+        for _ in 0..max_steps {
+            match self.cpu.pc() {
+                0x0000 => return Ok(()),
+                BDOS => self.bdos_call(),
+                _ => {}
+            }
+
+            self.cycles += self.cpu.step()? as u64;
+
+            if let Some(CPUEvent::Halt) = self.cpu.event() {
+                return Ok(());
+            }
+        }
+
+        Err(Error::StepLimitExceeded { steps: max_steps })
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    fn bdos_call(&mut self) {
+        match self.cpu.register(Register::C) {
+            2 => self.output.push(self.cpu.register(Register::E) as char),
+            9 => {
+                // Bounded, so a string missing its terminator can't hang the run
+                let start = self.cpu.de();
+                let string = (0..=u16::MAX)
+                    .map(|i| self.cpu.bus[start.wrapping_add(i)])
+                    .take_while(|&c| c != b'$');
+                self.output.extend(string.map(char::from));
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,11 +1,17 @@
 use std::mem;
-use crate::{concat_u16, Result, Memory};
+use crate::{concat_u16, Error, Result, Bus, Memory};
+use crate::state::{StateReader, StateWriter};
 
 pub const CARRY_FLAG: u8 = 1 << 0;
 pub const PARITY_FLAG: u8 = 1 << 2;
+pub const AUX_CARRY_FLAG: u8 = 1 << 4;
 pub const ZERO_FLAG: u8 = 1 << 6;
 pub const SIGN_FLAG: u8 = 1 << 7;
 
+// Bit 1 of the flags byte always reads as 1, bits 3 and 5 always read as 0
+const PSW_FIXED_SET: u8 = 1 << 1;
+const PSW_MASK: u8 = CARRY_FLAG | PARITY_FLAG | AUX_CARRY_FLAG | ZERO_FLAG | SIGN_FLAG;
+
 macro_rules! mov {
     ($from:expr,$to:expr,$cycles:expr) => {
         {
@@ -13,7 +19,7 @@ macro_rules! mov {
             $cycles
         }
     };
-    ($from:expr,$to:expr) => { mov!($from, $to, 1) };
+    ($from:expr,$to:expr) => { mov!($from, $to, 5) };
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -22,17 +28,28 @@ pub enum InterruptStatus {
     Disabled,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    Flags,
+}
+
 #[derive(Debug, Clone)]
 pub enum Event {
     Halt,
-    PortWrite(u8, u8),
-    PortRead(u8),
 }
 
 #[derive(Debug, Clone)]
-pub struct CPU {
-    pub memory: Memory,
+pub struct CPU<B = Memory> {
+    pub bus: B,
     interrupt_status: InterruptStatus,
+    halted: bool,
     event: Option<Event>,
     flags: u8,
     pc: u16,
@@ -47,15 +64,24 @@ pub struct CPU {
 }
 
 impl CPU {
-    pub fn new(program: &[u8]) -> Self {
+    /// Creates a CPU with `program` at the start of its 8K ROM.
+    pub fn new(program: &[u8]) -> Result<Self> {
         let mut rom = [0; 0x2000];
-        for (i, val) in program.iter().enumerate() {
-            rom[i] = *val;
+        if program.is_empty() || program.len() > rom.len() {
+            return Err(Error::InvalidRomSize { size: program.len() });
         }
+        rom[..program.len()].copy_from_slice(program);
+
+        Ok(Self::with_bus(Memory::new(rom)))
+    }
+}
 
+impl<B: Bus> CPU<B> {
+    pub fn with_bus(bus: B) -> Self {
         Self {
-            memory: Memory::new(rom),
+            bus,
             interrupt_status: InterruptStatus::Enabled,
+            halted: false,
             event: None,
             flags: 0,
             pc: 0,
@@ -70,9 +96,10 @@ impl CPU {
         }
     }
 
+    /// Resets the registers. Whatever is on the bus is left for its owner to reset.
     pub fn reset(&mut self) {
-        self.memory.reset_ram();
         self.interrupt_status = InterruptStatus::Enabled;
+        self.halted = false;
         self.event = None;
         self.flags = 0;
         self.pc = 0;
@@ -86,13 +113,20 @@ impl CPU {
         self.l = 0;
     }
 
+    /// Also wakes a halted CPU, if interrupts are enabled.
     pub fn interrupt(&mut self, interrupt_num: u8) {
         if let InterruptStatus::Enabled = self.interrupt_status {
+            self.halted = false;
             self.rst(interrupt_num);
         }
     }
 
+    /// A halted CPU executes nothing and idles for 4 cycles per step until interrupted.
     pub fn step(&mut self) -> Result<u32> {
+        if self.halted {
+            return Ok(4);
+        }
+
         let opcode = self.read_pc();
 
         macro_rules! mvi {
@@ -102,21 +136,21 @@ impl CPU {
                     $cycles
                 }
             };
-            ($to:expr) => { mvi!($to, 2) };
+            ($to:expr) => { mvi!($to, 7) };
         }
 
         macro_rules! ret {
             () => {
                 {
                     self.pc = self.stack_pop_u16();
-                    3
+                    10
                 }
             };
             (!$flag:expr) => {
-                if self.flag($flag) == 0 { ret!() } else { 1 }
+                if self.flag($flag) == 0 { ret!() + 1 } else { 5 }
             };
             ($flag:expr) => {
-                if self.flag($flag) != 0 { ret!() } else { 1 }
+                if self.flag($flag) != 0 { ret!() + 1 } else { 5 }
             };
         }
 
@@ -125,7 +159,7 @@ impl CPU {
                 {
                     self.stack_push($hi);
                     self.stack_push($lo);
-                    3
+                    11
                 }
             };
         }
@@ -135,35 +169,36 @@ impl CPU {
                 {
                     $lo = self.stack_pop();
                     $hi = self.stack_pop();
-                    3
+                    10
                 }
             };
         }
 
         Ok(match opcode {
             // Misc/control instructions
-            0x00 | 0x10 | 0x20 | 0x30 | 0x08 | 0x18 | 0x28 | 0x38 => 1, // NOP
+            0x00 | 0x10 | 0x20 | 0x30 | 0x08 | 0x18 | 0x28 | 0x38 => 4, // NOP
             0x76 => {                                                   // HLT
+                self.halted = true;
                 self.event = Some(Event::Halt);
-                1
+                7
             }
             0xD3 => {                                                   // OUT   d8
                 let port = self.read_pc();
-                self.event = Some(Event::PortWrite(port, self.a));
-                3
+                self.bus.output(port, self.a)?;
+                10
             }
             0xDB => {                                                   // IN    d8
                 let port = self.read_pc();
-                self.event = Some(Event::PortRead(port));
-                3
+                self.a = self.bus.input(port)?;
+                10
             }
             0xF3 => {                                                   // DI
                 self.interrupt_status = InterruptStatus::Disabled;
-                1
+                4
             }
             0xFB => {                                                   // EI
                 self.interrupt_status = InterruptStatus::Enabled;
-                1
+                4
             }
 
             // Jumps/calls
@@ -177,7 +212,7 @@ impl CPU {
             0xF2 => self.jmp_if_not(SIGN_FLAG),                         // JP    a16
             0xC3 | 0xCB => {                                            // JMP   a16
                 self.pc = self.read_pc_u16();
-                3
+                10
             }
             0xC4 => self.call_if_not(ZERO_FLAG),                        // CNZ   a16
             0xD4 => self.call_if_not(CARRY_FLAG),                       // CNC   a16
@@ -198,7 +233,7 @@ impl CPU {
             0xC9 | 0xD9 => ret!(),                                  // RET
             0xE9 => {                                                   // PCHL
                 self.pc = concat_u16!(self.h, self.l);
-                1
+                5
             }
             0xCA => self.jmp_if(ZERO_FLAG),                             // JZ    a16
             0xDA => self.jmp_if(CARRY_FLAG),                            // JC    a16
@@ -215,17 +250,17 @@ impl CPU {
 
             // 8-bit load/store/move instructions
             0x12 => {                                                   // STAX  D
-                *self.de_val_mut() = self.a;
-                2
+                self.bus.write(self.de(), self.a);
+                7
             }
             0x02 => {                                                   // STAX  B
-                *self.bc_val_mut() = self.a;
-                2
+                self.bus.write(self.bc(), self.a);
+                7
             }
             0x32 => {                                                   // STA   a16
                 let adr = self.read_pc_u16();
-                self.memory[adr] = self.a;
-                4
+                self.bus.write(adr, self.a);
+                13
             }
             0x06 => mvi!(self.b),                                                   // MVI   B,d8
             0x0E => mvi!(self.c),                                                   // MVI   C,d8
@@ -233,251 +268,269 @@ impl CPU {
             0x1E => mvi!(self.e),                                                   // MVI   E,d8
             0x26 => mvi!(self.h),                                                   // MVI   H,d8
             0x2E => mvi!(self.l),                                                   // MVI   L,d8
-            0x36 => mvi!(*self.m_val_mut(), 3),                                     // MVI   M,d8
+            0x36 => {                                                   // MVI   M,d8
+                let d8 = self.read_pc();
+                self.set_m_val(d8);
+                10
+            }
             0x3E => mvi!(self.a),                                                   // MVI   A,d8
             0x0A => {                                                   // LDAX  B
                 self.a = self.bc_val();
-                2
+                7
             }
             0x1A => {                                                   // LDAX  D
                 self.a = self.de_val();
-                2
+                7
             }
             0x3A => {                                                   // LDA   a16
                 let adr = self.read_pc_u16();
-                self.a = self.memory[adr];
-                4
+                self.a = self.bus.read(adr);
+                13
             }
-            0x40 => mov!(self.b, self.b),                     // MOV   B,B
+            0x40 => 5,                                        // MOV   B,B
             0x41 => mov!(self.c, self.b),                     // MOV   B,C
             0x42 => mov!(self.d, self.b),                     // MOV   B,D
             0x43 => mov!(self.e, self.b),                     // MOV   B,E
             0x44 => mov!(self.h, self.b),                     // MOV   B,H
             0x45 => mov!(self.l, self.b),                     // MOV   B,L
-            0x46 => mov!(self.m_val(), self.b, 2),             // MOV   B,M
+            0x46 => mov!(self.m_val(), self.b, 7),             // MOV   B,M
             0x47 => mov!(self.a, self.b),                     // MOV   B,A
             0x48 => mov!(self.b, self.c),                     // MOV   C,B
-            0x49 => mov!(self.c, self.c),                     // MOV   C,C
+            0x49 => 5,                                        // MOV   C,C
             0x4A => mov!(self.d, self.c),                     // MOV   C,D
             0x4B => mov!(self.e, self.c),                     // MOV   C,E
             0x4C => mov!(self.h, self.c),                     // MOV   C,H
             0x4D => mov!(self.l, self.c),                     // MOV   C,L
-            0x4E => mov!(self.m_val(), self.c, 2),             // MOV   C,M
+            0x4E => mov!(self.m_val(), self.c, 7),             // MOV   C,M
             0x4F => mov!(self.a, self.c),                     // MOV   C,A
             0x50 => mov!(self.b, self.d),                     // MOV   D,B
             0x51 => mov!(self.c, self.d),                     // MOV   D,C
-            0x52 => mov!(self.d, self.d),                     // MOV   D,D
+            0x52 => 5,                                        // MOV   D,D
             0x53 => mov!(self.e, self.d),                     // MOV   D,E
             0x54 => mov!(self.h, self.d),                     // MOV   D,H
             0x55 => mov!(self.l, self.d),                     // MOV   D,L
-            0x56 => mov!(self.m_val(), self.d, 2),             // MOV   D,M
+            0x56 => mov!(self.m_val(), self.d, 7),             // MOV   D,M
             0x57 => mov!(self.a, self.d),                     // MOV   D,A
             0x58 => mov!(self.b, self.e),                     // MOV   E,B
             0x59 => mov!(self.c, self.e),                     // MOV   E,C
             0x5A => mov!(self.d, self.e),                     // MOV   E,D
-            0x5B => mov!(self.e, self.e),                     // MOV   E,E
+            0x5B => 5,                                        // MOV   E,E
             0x5C => mov!(self.h, self.e),                     // MOV   E,H
             0x5D => mov!(self.l, self.e),                     // MOV   E,L
-            0x5E => mov!(self.m_val(), self.e, 2),             // MOV   E,M
+            0x5E => mov!(self.m_val(), self.e, 7),             // MOV   E,M
             0x5F => mov!(self.a, self.e),                     // MOV   E,A
             0x60 => mov!(self.b, self.h),                     // MOV   H,B
             0x61 => mov!(self.c, self.h),                     // MOV   H,C
             0x62 => mov!(self.d, self.h),                     // MOV   H,D
             0x63 => mov!(self.e, self.h),                     // MOV   H,E
-            0x64 => mov!(self.h, self.h),                     // MOV   H,H
+            0x64 => 5,                                        // MOV   H,H
             0x65 => mov!(self.l, self.h),                     // MOV   H,L
-            0x66 => mov!(self.m_val(), self.h, 2),             // MOV   H,M
+            0x66 => mov!(self.m_val(), self.h, 7),             // MOV   H,M
             0x67 => mov!(self.a, self.h),                     // MOV   H,A
             0x68 => mov!(self.b, self.l),                     // MOV   L,B
             0x69 => mov!(self.c, self.l),                     // MOV   L,C
             0x6A => mov!(self.d, self.l),                     // MOV   L,D
             0x6B => mov!(self.e, self.l),                     // MOV   L,E
             0x6C => mov!(self.h, self.l),                     // MOV   L,H
-            0x6D => mov!(self.l, self.l),                     // MOV   L,L
-            0x6E => mov!(self.m_val(), self.l, 2),             // MOV   L,M
+            0x6D => 5,                                        // MOV   L,L
+            0x6E => mov!(self.m_val(), self.l, 7),             // MOV   L,M
             0x6F => mov!(self.a, self.l),                     // MOV   L,A
-            0x70 => mov!(self.b, *self.m_val_mut(), 2),         // MOV   M,B
-            0x71 => mov!(self.c, *self.m_val_mut(), 2),         // MOV   M,C
-            0x72 => mov!(self.d, *self.m_val_mut(), 2),         // MOV   M,D
-            0x73 => mov!(self.e, *self.m_val_mut(), 2),         // MOV   M,E
-            0x74 => mov!(self.h, *self.m_val_mut(), 2),         // MOV   M,H
-            0x75 => mov!(self.l, *self.m_val_mut(), 2),         // MOV   M,L
-            0x77 => mov!(self.a, *self.m_val_mut(), 2),         // MOV   M,A
+            0x70 => { self.set_m_val(self.b); 7 }             // MOV   M,B
+            0x71 => { self.set_m_val(self.c); 7 }             // MOV   M,C
+            0x72 => { self.set_m_val(self.d); 7 }             // MOV   M,D
+            0x73 => { self.set_m_val(self.e); 7 }             // MOV   M,E
+            0x74 => { self.set_m_val(self.h); 7 }             // MOV   M,H
+            0x75 => { self.set_m_val(self.l); 7 }             // MOV   M,L
+            0x77 => { self.set_m_val(self.a); 7 }             // MOV   M,A
             0x78 => mov!(self.b, self.a),                     // MOV   A,B
             0x79 => mov!(self.c, self.a),                     // MOV   A,C
             0x7A => mov!(self.d, self.a),                     // MOV   A,D
             0x7B => mov!(self.e, self.a),                     // MOV   A,E
             0x7C => mov!(self.h, self.a),                     // MOV   A,H
             0x7D => mov!(self.l, self.a),                     // MOV   A,L
-            0x7E => mov!(self.m_val(), self.a, 2),             // MOV   A,M
-            0x7F => mov!(self.a, self.a),                   // MOV   A,A
+            0x7E => mov!(self.m_val(), self.a, 7),             // MOV   A,M
+            0x7F => 5,                                      // MOV   A,A
 
             // 16-bit load/store/move instructions
             0x01 => {                                                   // LXI   B,d16
                 self.c = self.read_pc();
                 self.b = self.read_pc();
-                3
+                10
             }
             0x11 => {                                                   // LXI   D,d16
                 self.e = self.read_pc();
                 self.d = self.read_pc();
-                3
+                10
             }
             0x21 => {                                                   // LXI   H,d16
                 self.l = self.read_pc();
                 self.h = self.read_pc();
-                3
+                10
             }
             0x31 => {                                                   // LXI   SP,d16
                 self.sp = self.read_pc_u16();
-                3
+                10
             }
             0x22 => {                                                   // SHLD
                 let adr = self.read_pc_u16();
-                self.memory[adr] = self.l;
-                self.memory[adr + 1] = self.h;
-                5
+                self.bus.write(adr, self.l);
+                self.bus.write(adr.wrapping_add(1), self.h);
+                16
             }
             0x2A => {                                                   // LHLD
                 let adr = self.read_pc_u16();
-                self.l = self.memory[adr];
-                self.h = self.memory[adr + 1];
-                5
+                self.l = self.bus.read(adr);
+                self.h = self.bus.read(adr.wrapping_add(1));
+                16
             }
             0xC1 => pop!(self.b, self.c),                                                   // POP  B
             0xD1 => pop!(self.d, self.e),                                                   // POP  D
             0xE1 => pop!(self.h, self.l),                                                   // POP  H
-            0xF1 => pop!(self.a, self.flags),                                               // POP  PSW
+            0xF1 => {                                                   // POP  PSW
+                self.flags = self.stack_pop() & PSW_MASK;
+                self.a = self.stack_pop();
+                10
+            }
             0xC5 => push!(self.b, self.c),                                                   // PUSH  B
             0xD5 => push!(self.d, self.e),                                                   // PUSH  D
             0xE5 => push!(self.h, self.l),                                                   // PUSH  H
-            0xF5 => push!(self.a, self.flags),                                               // PUSH  PSW
+            0xF5 => push!(self.a, self.psw()),                                                 // PUSH  PSW
             0xE3 => {                                                   // XTHL
-                mem::swap(&mut self.h, &mut self.memory[self.sp + 1]);
-                mem::swap(&mut self.l, &mut self.memory[self.sp]);
-                5
+                let (l, h) = (self.bus.read(self.sp), self.bus.read(self.sp.wrapping_add(1)));
+                self.bus.write(self.sp, self.l);
+                self.bus.write(self.sp.wrapping_add(1), self.h);
+                (self.l, self.h) = (l, h);
+                18
             }
             0xF9 => {                                                   // SPHL
                 self.sp = self.m();
-                1
+                5
             }
             0xEB => {                                                   // XCHG
                 mem::swap(&mut self.h, &mut self.d);
                 mem::swap(&mut self.l, &mut self.e);
-                1
+                4
             }
 
             // 8-bit arithmetic/logical instructions
             0x04 => {                                                   // INR   B
                 self.b = self.inr(self.b);
-                1
+                5
             }
             0x0C => {                                                   // INR   C
                 self.c = self.inr(self.c);
-                1
+                5
             }
             0x14 => {                                                   // INR   D
                 self.d = self.inr(self.d);
-                1
+                5
             }
             0x1C => {                                                   // INR   E
                 self.e = self.inr(self.e);
-                1
+                5
             }
             0x24 => {                                                   // INR   H
                 self.h = self.inr(self.h);
-                1
+                5
             }
             0x2C => {                                                   // INR   L
                 self.l = self.inr(self.l);
-                1
+                5
             }
             0x34 => {                                                   // INR   M
-                *self.m_val_mut() = self.inr(self.m_val());
-                3
+                let val = self.inr(self.m_val());
+                self.set_m_val(val);
+                10
             }
             0x3C => {                                                   // INR   A
                 self.a = self.inr(self.a);
-                1
+                5
             }
             0x05 => {                                                   // DCR   B
                 self.b = self.dcr(self.b);
-                1
+                5
             }
             0x0D => {                                                   // DCR   C
                 self.c = self.dcr(self.c);
-                1
+                5
             }
             0x15 => {                                                   // DCR   D
                 self.d = self.dcr(self.d);
-                1
+                5
             }
             0x1D => {                                                   // DCR   E
                 self.e = self.dcr(self.e);
-                1
+                5
             }
             0x25 => {                                                   // DCR   H
                 self.h = self.dcr(self.h);
-                1
+                5
             }
             0x2D => {                                                   // DCR   L
                 self.l = self.dcr(self.l);
-                1
+                5
             }
             0x35 => {                                                   // DCR   M
-                *self.m_val_mut() = self.dcr(self.m_val());
-                3
+                let val = self.dcr(self.m_val());
+                self.set_m_val(val);
+                10
             }
             0x3D => {                                                   // DCR   A
                 self.a = self.dcr(self.a);
-                1
+                5
             }
             0x07 => {                                                   // RLC
                 self.set_flag(CARRY_FLAG, self.a & (1 << 7));
                 self.a = self.a.rotate_left(1);
-                1
+                4
             }
             0x0F => {                                                   // RRC
                 self.set_flag(CARRY_FLAG, self.a & 1);
                 self.a = self.a.rotate_right(1);
-                1
+                4
             }
             0x17 => {                                                   // RAL
                 let carry = self.a & (1 << 7);
                 self.a = (self.a << 1) | self.flag(CARRY_FLAG);
                 self.set_flag(CARRY_FLAG, carry);
-                1
+                4
             }
             0x1F => {                                                   // RAR
                 let carry = self.a & 1;
                 self.a = (self.a >> 1) | (self.flag(CARRY_FLAG) << 7);
                 self.set_flag(CARRY_FLAG, carry);
-                1
+                4
             }
             0x27 => {                                                   // DAA
-                if self.a & 0x0F > 9 {
-                    self.a += 6;
+                let mut correction = 0;
+                let mut carry = self.flag(CARRY_FLAG);
+
+                if self.a & 0x0F > 9 || self.flag(AUX_CARRY_FLAG) != 0 {
+                    correction |= 0x06;
                 }
 
-                if self.a & 0xF0 > 0x90 {
-                    let (result, carry) = self.a.overflowing_add(0x60);
-                    self.set_flags(self.a, carry as u8);
-                    self.a = result;
+                if self.a > 0x99 || carry != 0 {
+                    correction |= 0x60;
+                    carry = 1;
                 }
 
-                1
+                let result = self.a.wrapping_add(correction);
+                self.set_flags(result, carry);
+                self.set_flag(AUX_CARRY_FLAG, ((self.a & 0x0F) + (correction & 0x0F) > 0x0F) as u8);
+                self.a = result;
+                4
             }
             0x37 => {                                                   // STC
                 self.set_flag(CARRY_FLAG, 1);
-                1
+                4
             }
             0x2F => {                                                   // CMA
                 self.a = !self.a;
-                1
+                4
             }
             0x3F => {                                                   // CMC
                 self.flags ^= CARRY_FLAG;
-                1
+                4
             }
             0x80 => self.add_a(self.b),                                 // ADD   B
             0x81 => self.add_a(self.c),                                 // ADD   C
@@ -487,20 +540,20 @@ impl CPU {
             0x85 => self.add_a(self.l),                                 // ADD   L
             0x86 => {                                                         // ADD   M
                 self.add_a(self.m_val());
-                2
+                7
             }
             0x87 => self.add_a(self.a),                                 // ADD   A
-            0x88 => self.add_a(self.b + self.flag(CARRY_FLAG)),         // ADC   B
-            0x89 => self.add_a(self.c + self.flag(CARRY_FLAG)),         // ADC   C
-            0x8A => self.add_a(self.d + self.flag(CARRY_FLAG)),         // ADC   D
-            0x8B => self.add_a(self.e + self.flag(CARRY_FLAG)),         // ADC   E
-            0x8C => self.add_a(self.h + self.flag(CARRY_FLAG)),         // ADC   H
-            0x8D => self.add_a(self.l + self.flag(CARRY_FLAG)),         // ADC   L
+            0x88 => self.adc_a(self.b, self.flag(CARRY_FLAG)),         // ADC   B
+            0x89 => self.adc_a(self.c, self.flag(CARRY_FLAG)),         // ADC   C
+            0x8A => self.adc_a(self.d, self.flag(CARRY_FLAG)),         // ADC   D
+            0x8B => self.adc_a(self.e, self.flag(CARRY_FLAG)),         // ADC   E
+            0x8C => self.adc_a(self.h, self.flag(CARRY_FLAG)),         // ADC   H
+            0x8D => self.adc_a(self.l, self.flag(CARRY_FLAG)),         // ADC   L
             0x8E => {                                                   // ADC   M
-                self.add_a(self.m_val() + self.flag(CARRY_FLAG));
-                2
+                self.adc_a(self.m_val(), self.flag(CARRY_FLAG));
+                7
             }
-            0x8F => self.add_a(self.a + self.flag(CARRY_FLAG)),         // ADC   A
+            0x8F => self.adc_a(self.a, self.flag(CARRY_FLAG)),         // ADC   A
             0x90 => self.sub_a(self.b),                                 // SUB   B
             0x91 => self.sub_a(self.c),                                 // SUB   C
             0x92 => self.sub_a(self.d),                                 // SUB   D
@@ -509,20 +562,20 @@ impl CPU {
             0x95 => self.sub_a(self.l),                                 // SUB   L
             0x96 => {                                                   // SUB   M
                 self.sub_a(self.m_val());
-                2
+                7
             }
             0x97 => self.sub_a(self.a),                                 // SUB   A
-            0x98 => self.sub_a(self.b + self.flag(CARRY_FLAG)),         // SBB   B
-            0x99 => self.sub_a(self.c + self.flag(CARRY_FLAG)),         // SBB   C
-            0x9A => self.sub_a(self.d + self.flag(CARRY_FLAG)),         // SBB   D
-            0x9B => self.sub_a(self.e + self.flag(CARRY_FLAG)),         // SBB   E
-            0x9C => self.sub_a(self.h + self.flag(CARRY_FLAG)),         // SBB   H
-            0x9D => self.sub_a(self.l + self.flag(CARRY_FLAG)),         // SBB   L
+            0x98 => self.sbb_a(self.b, self.flag(CARRY_FLAG)),         // SBB   B
+            0x99 => self.sbb_a(self.c, self.flag(CARRY_FLAG)),         // SBB   C
+            0x9A => self.sbb_a(self.d, self.flag(CARRY_FLAG)),         // SBB   D
+            0x9B => self.sbb_a(self.e, self.flag(CARRY_FLAG)),         // SBB   E
+            0x9C => self.sbb_a(self.h, self.flag(CARRY_FLAG)),         // SBB   H
+            0x9D => self.sbb_a(self.l, self.flag(CARRY_FLAG)),         // SBB   L
             0x9E => {                                                   // SBB   M
-                self.sub_a(self.m_val() + self.flag(CARRY_FLAG));
-                2
+                self.sbb_a(self.m_val(), self.flag(CARRY_FLAG));
+                7
             }
-            0x9F => self.sub_a(self.a + self.flag(CARRY_FLAG)),         // SBB   A
+            0x9F => self.sbb_a(self.a, self.flag(CARRY_FLAG)),         // SBB   A
             0xA0 => self.and_a(self.b),                                 // ANA   B
             0xA1 => self.and_a(self.c),                                 // ANA   C
             0xA2 => self.and_a(self.d),                                 // ANA   D
@@ -531,7 +584,7 @@ impl CPU {
             0xA5 => self.and_a(self.l),                                 // ANA   L
             0xA6 => {                                                   // ANA   M
                 self.and_a(self.m_val());
-                2
+                7
             }
             0xA7 => self.and_a(self.a),                                 // ANA   A
             0xA8 => self.xor_a(self.b),                                 // XRA   B
@@ -542,7 +595,7 @@ impl CPU {
             0xAD => self.xor_a(self.l),                                 // XRA   L
             0xAE => {                                                   // XRA   M
                 self.xor_a(self.m_val());
-                2
+                7
             }
             0xAF => self.xor_a(self.a),                                 // XRA   A
             0xB0 => self.or_a(self.b),                                  // ORA   B
@@ -553,7 +606,7 @@ impl CPU {
             0xB5 => self.or_a(self.l),                                  // ORA   L
             0xB6 => {                                                   // ORA   M
                 self.or_a(self.m_val());
-                2
+                7
             }
             0xB7 => self.or_a(self.a),                                  // ORA   A
             0xB8 => self.cmp_a(self.b),                                 // CMP   B
@@ -564,48 +617,48 @@ impl CPU {
             0xBD => self.cmp_a(self.l),                                 // CMP   L
             0xBE => {                                                   // CMP   M
                 self.cmp_a(self.m_val());
-                2
+                7
             }
             0xBF => self.cmp_a(self.a),                                 // CMP   A
             0xC6 => {                                                   // ADI   d8
                 let d8 = self.read_pc();
                 self.add_a(d8);
-                2
+                7
             }
             0xD6 => {                                                   // SUI   d8
                 let d8 = self.read_pc();
                 self.sub_a(d8);
-                2
+                7
             }
             0xE6 => {                                                   // ANI   d8
                 let d8 = self.read_pc();
                 self.and_a(d8);
-                2
+                7
             }
             0xF6 => {                                                   // ORI   d8
                 let d8 = self.read_pc();
                 self.or_a(d8);
-                2
+                7
             }
             0xCE => {                                                   // ACI   d8
                 let d8 = self.read_pc();
-                self.add_a(d8 + self.flag(CARRY_FLAG));
-                2
+                self.adc_a(d8, self.flag(CARRY_FLAG));
+                7
             }
             0xDE => {                                                   // SBI   d8
                 let d8 = self.read_pc();
-                self.sub_a(d8 + self.flag(CARRY_FLAG));
-                2
+                self.sbb_a(d8, self.flag(CARRY_FLAG));
+                7
             }
             0xEE => {                                                   // XRI   d8
                 let d8 = self.read_pc();
                 self.xor_a(d8);
-                2
+                7
             }
             0xFE => {                                                   // CPI   d8
                 let d8 = self.read_pc();
                 self.cmp_a(d8);
-                2
+                7
             }
 
             // 16-bit arithmetic/logical instructions
@@ -614,7 +667,7 @@ impl CPU {
             0x23 => Self::inx(&mut self.h, &mut self.l),                // INX   H
             0x33 => {                                                   // INX   SP
                 self.sp = self.sp.wrapping_add(1);
-                1
+                5
             }
             0x09 => self.dad(self.b, self.c),                           // DAD   B
             0x19 => self.dad(self.d, self.e),                           // DAD   D
@@ -625,106 +678,209 @@ impl CPU {
             0x2B => Self::dcx(&mut self.h, &mut self.l),                // DCX   H
             0x3B => {                                                   // DCX   SP
                 self.sp = self.sp.wrapping_sub(1);
-                1
+                5
             }
         })
     }
 
     pub fn event(&mut self) -> Option<Event> {
-        mem::replace(&mut self.event, None)
+        self.event.take()
+    }
+
+    pub fn psw(&self) -> u8 {
+        (self.flags & PSW_MASK) | PSW_FIXED_SET
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.sp
     }
 
-    pub fn port_in(&mut self, val: u8) {
-        self.a = val;
+    pub fn set_sp(&mut self, sp: u16) {
+        self.sp = sp;
+    }
+
+    pub fn register(&self, register: Register) -> u8 {
+        match register {
+            Register::A => self.a,
+            Register::B => self.b,
+            Register::C => self.c,
+            Register::D => self.d,
+            Register::E => self.e,
+            Register::H => self.h,
+            Register::L => self.l,
+            Register::Flags => self.psw(),
+        }
+    }
+
+    pub fn set_register(&mut self, register: Register, val: u8) {
+        match register {
+            Register::A => self.a = val,
+            Register::B => self.b = val,
+            Register::C => self.c = val,
+            Register::D => self.d = val,
+            Register::E => self.e = val,
+            Register::H => self.h = val,
+            Register::L => self.l = val,
+            Register::Flags => self.flags = val & PSW_MASK,
+        }
+    }
+
+    pub fn interrupts_enabled(&self) -> bool {
+        self.interrupt_status == InterruptStatus::Enabled
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        for val in [self.a, self.b, self.c, self.d, self.e, self.h, self.l, self.psw()] {
+            writer.write_u8(val);
+        }
+        writer.write_u16(self.sp);
+        writer.write_u16(self.pc);
+        writer.write_u8(self.interrupts_enabled() as u8);
+        writer.write_u8(self.halted as u8);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.a = reader.read_u8()?;
+        self.b = reader.read_u8()?;
+        self.c = reader.read_u8()?;
+        self.d = reader.read_u8()?;
+        self.e = reader.read_u8()?;
+        self.h = reader.read_u8()?;
+        self.l = reader.read_u8()?;
+        self.flags = reader.read_u8()? & PSW_MASK;
+        self.sp = reader.read_u16()?;
+        self.pc = reader.read_u16()?;
+        self.interrupt_status = match reader.read_u8()? {
+            0 => InterruptStatus::Disabled,
+            _ => InterruptStatus::Enabled,
+        };
+        self.halted = reader.read_u8()? != 0;
+        self.event = None;
+        Ok(())
     }
 
     fn jmp_if(&mut self, flag: u8) -> u32 {
         let adr = self.read_pc_u16();
         if self.flag(flag) != 0 { self.pc = adr; }
-        3
+        10
     }
 
     fn jmp_if_not(&mut self, flag: u8) -> u32 {
         let adr = self.read_pc_u16();
         if self.flag(flag) == 0 { self.pc = adr; }
-        3
+        10
     }
 
     fn rst(&mut self, val: u8) -> u32 {
-        self.call((val as u16) << 3)
+        self.call((val as u16) << 3);
+        11
     }
 
     fn call(&mut self, adr: u16) -> u32 {
         self.stack_push_u16(self.pc);
         self.pc = adr;
-        5
+        17
     }
 
     fn call_if(&mut self, flag: u8) -> u32 {
         let adr = self.read_pc_u16();
-        if self.flag(flag) != 0 { self.call(adr) } else { 3 }
+        if self.flag(flag) != 0 { self.call(adr) } else { 11 }
     }
 
     fn call_if_not(&mut self, flag: u8) -> u32 {
         let adr = self.read_pc_u16();
-        if self.flag(flag) == 0 { self.call(adr) } else { 3 }
+        if self.flag(flag) == 0 { self.call(adr) } else { 11 }
     }
 
     fn inr(&mut self, val: u8) -> u8 {
         let result = val.wrapping_add(1);
         self.set_flags(result, self.flag(CARRY_FLAG));
+        self.set_flag(AUX_CARRY_FLAG, (result & 0x0F == 0) as u8);
         result
     }
 
     fn dcr(&mut self, val: u8) -> u8 {
         let result = val.wrapping_sub(1);
         self.set_flags(result, self.flag(CARRY_FLAG));
+        self.set_flag(AUX_CARRY_FLAG, (result & 0x0F != 0x0F) as u8);
         result
     }
 
-    fn add_a(&mut self, right: u8) -> u32 {
-        let (result, overflow) = self.a.overflowing_add(right);
-        self.set_flags(result, overflow as u8);
-        self.a = result;
-        1
+    fn add_a(&mut self, val: u8) -> u32 {
+        self.adc_a(val, 0)
+    }
+
+    fn adc_a(&mut self, val: u8, carry: u8) -> u32 {
+        self.a = self.add_with_carry(val, carry);
+        4
     }
 
     fn sub_a(&mut self, val: u8) -> u32 {
-        let (result, underflow) = self.a.overflowing_sub(val);
-        self.set_flags(result, underflow as u8);
-        self.a = result;
-        1
+        self.sbb_a(val, 0)
+    }
+
+    fn sbb_a(&mut self, val: u8, borrow: u8) -> u32 {
+        // The 8080 subtracts by adding the one's complement, so the carry
+        // flag ends up inverted to represent the borrow
+        self.a = self.add_with_carry(!val, borrow ^ 1);
+        self.flags ^= CARRY_FLAG;
+        4
     }
 
     fn and_a(&mut self, val: u8) -> u32 {
+        let aux_carry = (self.a | val) & 0x08;
         self.a &= val;
         self.set_flags(self.a, 0);
-        1
+        self.set_flag(AUX_CARRY_FLAG, aux_carry);
+        4
     }
 
     fn xor_a(&mut self, val: u8) -> u32 {
         self.a ^= val;
         self.set_flags(self.a, 0);
-        1
+        self.set_flag(AUX_CARRY_FLAG, 0);
+        4
     }
 
     fn or_a(&mut self, val: u8) -> u32 {
         self.a |= val;
         self.set_flags(self.a, 0);
-        1
+        self.set_flag(AUX_CARRY_FLAG, 0);
+        4
     }
 
     fn cmp_a(&mut self, val: u8) -> u32 {
-        let (result, underflow) = self.a.overflowing_sub(val);
-        self.set_flags(result, underflow as u8);
-        1
+        self.add_with_carry(!val, 1);
+        self.flags ^= CARRY_FLAG;
+        4
+    }
+
+    fn add_with_carry(&mut self, val: u8, carry: u8) -> u8 {
+        let sum = self.a as u16 + val as u16 + carry as u16;
+        let result = sum as u8;
+
+        self.set_flags(result, (sum > 0xFF) as u8);
+        self.set_flag(AUX_CARRY_FLAG, ((self.a & 0x0F) + (val & 0x0F) + carry > 0x0F) as u8);
+        result
     }
 
     fn inx(hi: &mut u8, lo: &mut u8) -> u32 {
         let (result_lo, carry) = lo.overflowing_add(1);
         *lo = result_lo;
         *hi = hi.wrapping_add(carry as u8);
-        1
+        5
     }
 
     fn dad(&mut self, hi: u8, lo: u8) -> u32 {
@@ -735,19 +891,19 @@ impl CPU {
         self.h = (result >> 8) as u8;
         self.l = (result & 0xFF) as u8;
         self.set_flag(CARRY_FLAG, carry as u8);
-        3
+        10
     }
 
     fn dcx(hi: &mut u8, lo: &mut u8) -> u32 {
         let (result_lo, carry) = lo.overflowing_sub(1);
         *lo = result_lo;
         *hi = hi.wrapping_sub(carry as u8);
-        1
+        5
     }
 
     fn stack_push(&mut self, val: u8) {
-        self.sp -= 1;
-        self.memory[self.sp] = val;
+        self.sp = self.sp.wrapping_sub(1);
+        self.bus.write(self.sp, val);
     }
 
     fn stack_push_u16(&mut self, val: u16) {
@@ -756,8 +912,8 @@ impl CPU {
     }
 
     fn stack_pop(&mut self) -> u8 {
-        let val = self.memory[self.sp];
-        self.sp += 1;
+        let val = self.bus.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
         val
     }
 
@@ -775,14 +931,14 @@ impl CPU {
     }
 
     fn read_pc(&mut self) -> u8 {
-        let val = self.memory[self.pc];
-        self.pc += 1;
+        let val = self.bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
         val
     }
 
     fn read_pc_u16(&mut self) -> u16 {
-        let val = concat_u16!(self.memory[self.pc + 1], self.memory[self.pc]);
-        self.pc += 2;
+        let val = concat_u16!(self.bus.read(self.pc.wrapping_add(1)), self.bus.read(self.pc));
+        self.pc = self.pc.wrapping_add(2);
         val
     }
 
@@ -800,28 +956,157 @@ impl CPU {
 
     fn bc(&self) -> u16 { concat_u16!(self.b, self.c) }
 
-    fn bc_val(&self) -> u8 { self.memory[self.bc()] }
+    fn bc_val(&self) -> u8 { self.bus.read(self.bc()) }
+
+    pub(crate) fn de(&self) -> u16 { concat_u16!(self.d, self.e) }
 
-    fn bc_val_mut(&mut self) -> &mut u8 {
-        let adr = self.bc();
-        &mut self.memory[adr]
+    fn de_val(&self) -> u8 { self.bus.read(self.de()) }
+
+    fn m(&self) -> u16 { concat_u16!(self.h, self.l) }
+
+    fn m_val(&self) -> u8 { self.bus.read(self.m()) }
+
+    fn set_m_val(&mut self, val: u8) { self.bus.write(self.m(), val) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(program: &[u8], steps: usize) -> CPU {
+        let mut cpu = CPU::new(program).unwrap();
+        cpu.sp = 0x2400;
+        for _ in 0..steps {
+            cpu.step().unwrap();
+        }
+        cpu
     }
 
-    fn de(&self) -> u16 { concat_u16!(self.d, self.e) }
+    #[test]
+    fn test_add_aux_carry() {
+        let cpu = run(&[0x3E, 0x0F, 0xC6, 0x01], 2);  // MVI A,0Fh; ADI 01h
+        assert_eq!(cpu.a, 0x10);
+        assert_ne!(cpu.flags & AUX_CARRY_FLAG, 0);
+        assert_eq!(cpu.flags & CARRY_FLAG, 0);
 
-    fn de_val(&self) -> u8 { self.memory[self.de()] }
+        let cpu = run(&[0x3E, 0x0E, 0x37, 0xCE, 0x01], 3);  // MVI A,0Eh; STC; ACI 01h
+        assert_eq!(cpu.a, 0x10);
+        assert_ne!(cpu.flags & AUX_CARRY_FLAG, 0);
 
-    fn de_val_mut(&mut self) -> &mut u8 {
-        let adr = self.de();
-        &mut self.memory[adr]
+        let cpu = run(&[0x3E, 0xFF, 0x37, 0xCE, 0xFF], 3);  // MVI A,FFh; STC; ACI FFh
+        assert_eq!(cpu.a, 0xFF);
+        assert_ne!(cpu.flags & CARRY_FLAG, 0);
     }
 
-    fn m(&self) -> u16 { concat_u16!(self.h, self.l) }
+    #[test]
+    fn test_sub_aux_carry() {
+        let cpu = run(&[0x3E, 0x10, 0xD6, 0x01], 2);  // MVI A,10h; SUI 01h
+        assert_eq!(cpu.a, 0x0F);
+        assert_eq!(cpu.flags & AUX_CARRY_FLAG, 0);
+        assert_eq!(cpu.flags & CARRY_FLAG, 0);
+
+        let cpu = run(&[0x3E, 0x05, 0xFE, 0x03], 2);  // MVI A,05h; CPI 03h
+        assert_eq!(cpu.a, 0x05);
+        assert_ne!(cpu.flags & AUX_CARRY_FLAG, 0);
+        assert_eq!(cpu.flags & CARRY_FLAG, 0);
+
+        let cpu = run(&[0x3E, 0x00, 0x37, 0xDE, 0x00], 3);  // MVI A,00h; STC; SBI 00h
+        assert_eq!(cpu.a, 0xFF);
+        assert_ne!(cpu.flags & CARRY_FLAG, 0);
+    }
+
+    #[test]
+    fn test_inr_dcr_aux_carry() {
+        let cpu = run(&[0x06, 0x0F, 0x04], 2);  // MVI B,0Fh; INR B
+        assert_ne!(cpu.flags & AUX_CARRY_FLAG, 0);
+
+        let cpu = run(&[0x06, 0x10, 0x05], 2);  // MVI B,10h; DCR B
+        assert_eq!(cpu.flags & AUX_CARRY_FLAG, 0);
+
+        let cpu = run(&[0x06, 0x11, 0x05], 2);  // MVI B,11h; DCR B
+        assert_ne!(cpu.flags & AUX_CARRY_FLAG, 0);
+    }
+
+    #[test]
+    fn test_daa() {
+        let cpu = run(&[0x3E, 0x19, 0xC6, 0x28, 0x27], 3);  // MVI A,19h; ADI 28h; DAA
+        assert_eq!(cpu.a, 0x47);
+        assert_eq!(cpu.flags & CARRY_FLAG, 0);
+
+        let cpu = run(&[0x3E, 0x99, 0xC6, 0x01, 0x27], 3);  // MVI A,99h; ADI 01h; DAA
+        assert_eq!(cpu.a, 0x00);
+        assert_ne!(cpu.flags & CARRY_FLAG, 0);
+        assert_ne!(cpu.flags & ZERO_FLAG, 0);
+    }
 
-    fn m_val(&self) -> u8 { self.memory[self.m()] }
+    #[test]
+    fn test_cycles() {
+        let mut cpu = CPU::new(&[
+            0x00,               // NOP
+            0x41,               // MOV   B,C
+            0x7E,               // MOV   A,M
+            0x36, 0x00,         // MVI   M,d8
+            0xC4, 0x0E, 0x00,   // CNZ   000Eh
+            0xCC, 0x0E, 0x00,   // CZ    000Eh
+            0xC3, 0x00, 0x00,   // JMP   0000h
+            0xC0,               // RNZ
+        ]).unwrap();
+        cpu.sp = 0x2400;
+        cpu.h = 0x20;
+
+        let cycles: Vec<u32> = (0..8).map(|_| cpu.step().unwrap()).collect();
+        assert_eq!(cycles, [4, 5, 7, 10, 17, 11, 11, 10]);
+        assert_eq!(cpu.pc, 0x0000);
+    }
+
+    #[test]
+    fn test_psw_fixed_bits() {
+        let cpu = run(&[0x01, 0xFF, 0xFF, 0xC5, 0xF1, 0xF5], 4);  // LXI B,FFFFh; PUSH B; POP PSW; PUSH PSW
+        assert_eq!(cpu.a, 0xFF);
+        assert_eq!(cpu.psw(), 0b1101_0111);
+        assert_eq!(cpu.bus[cpu.sp], 0b1101_0111);
+
+        let cpu = run(&[0x01, 0x00, 0x00, 0xC5, 0xF1], 3);  // LXI B,0000h; PUSH B; POP PSW
+        assert_eq!(cpu.psw(), 0b0000_0010);
+    }
+
+    /// 64K of RAM with every port echoing back one more than its number.
+    struct TestBus {
+        ram: Vec<u8>,
+        outputs: Vec<(u8, u8)>,
+    }
+
+    impl Bus for TestBus {
+        fn read(&self, adr: u16) -> u8 { self.ram[adr as usize] }
+        fn write(&mut self, adr: u16, val: u8) { self.ram[adr as usize] = val; }
+        fn input(&mut self, port: u8) -> Result<u8> { Ok(port + 1) }
+
+        fn output(&mut self, port: u8, val: u8) -> Result<()> {
+            if port == 0xFF {
+                return Err(Error::InvalidWritePort { port });
+            }
+            self.outputs.push((port, val));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_custom_bus() {
+        let mut ram = vec![0; 0x10000];
+        ram[..9].copy_from_slice(&[
+            0xDB, 0x04,         // IN    4
+            0xC6, 0x01,         // ADI   01h
+            0xD3, 0x07,         // OUT   7
+            0x32, 0x00, 0x80,   // STA   8000h
+        ]);
+        let mut cpu = CPU::with_bus(TestBus { ram, outputs: Vec::new() });
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.bus.outputs, [(7, 6)]);
+        assert_eq!(cpu.bus.ram[0x8000], 6);
 
-    fn m_val_mut(&mut self) -> &mut u8 {
-        let adr = self.m();
-        &mut self.memory[adr]
+        cpu.bus.ram[9..11].copy_from_slice(&[0xD3, 0xFF]);  // OUT FFh
+        assert!(matches!(cpu.step(), Err(Error::InvalidWritePort { port: 0xFF })));
     }
 }
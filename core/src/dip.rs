@@ -0,0 +1,69 @@
+const LIVES_MASK: u8 = 0b0000_0011;
+const BONUS_LIFE_MASK: u8 = 0b0000_1000;
+const COIN_INFO_MASK: u8 = 0b1000_0000;
+
+/// Cabinet DIP switches, read through input port 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DipSwitches {
+    /// Lives per game, from 3 to 6.
+    pub lives: u8,
+    /// Awards the bonus life at 1000 points instead of 1500.
+    pub bonus_life_at_1000: bool,
+    /// Shows the coin info on the attract screen.
+    pub coin_info: bool,
+}
+
+impl DipSwitches {
+    pub const MIN_LIVES: u8 = 3;
+    pub const MAX_LIVES: u8 = 6;
+
+    /// Bits of input port 2 set by the switches. The coin info switch is active low.
+    pub fn bits(&self) -> u8 {
+        let mut bits = (self.lives.clamp(Self::MIN_LIVES, Self::MAX_LIVES) - Self::MIN_LIVES) & LIVES_MASK;
+        if self.bonus_life_at_1000 {
+            bits |= BONUS_LIFE_MASK;
+        }
+        if !self.coin_info {
+            bits |= COIN_INFO_MASK;
+        }
+        bits
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            lives: Self::MIN_LIVES + (bits & LIVES_MASK),
+            bonus_life_at_1000: bits & BONUS_LIFE_MASK != 0,
+            coin_info: bits & COIN_INFO_MASK == 0,
+        }
+    }
+
+    /// Score at which the bonus life is awarded.
+    pub fn bonus_life(&self) -> u16 {
+        if self.bonus_life_at_1000 { 1000 } else { 1500 }
+    }
+}
+
+impl Default for DipSwitches {
+    fn default() -> Self {
+        Self { lives: 3, bonus_life_at_1000: false, coin_info: true }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bits() {
+        assert_eq!(DipSwitches::default().bits(), 0);
+
+        let dips = DipSwitches { lives: 6, bonus_life_at_1000: true, coin_info: false };
+        assert_eq!(dips.bits(), 0b1000_1011);
+        assert_eq!(DipSwitches::from_bits(dips.bits()), dips);
+
+        for lives in 3..=6 {
+            let dips = DipSwitches { lives, ..Default::default() };
+            assert_eq!(DipSwitches::from_bits(dips.bits()).lives, lives);
+        }
+    }
+}
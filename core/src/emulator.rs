@@ -1,17 +1,12 @@
-use std::mem;
-use crate::{concat_u16, Result, Error, CPU, CPUEvent, Button};
-
-macro_rules! check_sound_events {
-    ( $last_port:expr, $val:expr, $ev:expr, $(($msk:expr,$snd:expr)),* ) => {
-        $(
-            if $val & $msk != 0 && $last_port & $msk == 0 {
-                $ev = Some(Event::PlaySound($snd));
-            } else if $val & $msk == 0 && $last_port & $msk != 0 {
-                $ev = Some(Event::StopSound($snd))
-            }
-        )*
-    };
-}
+use std::collections::VecDeque;
+use crate::{Result, Error, Bus, CPU, CPUEvent, Button, DipSwitches, Memory, ShiftRegister};
+use crate::board::{Controls, IoBoard};
+use crate::machine::Machine;
+use crate::state::{StateReader, StateWriter};
+
+pub const CLOCK_SPEED: u32 = 2_000_000;
+pub const FPS: u32 = 60;
+pub const CYCLES_PER_FRAME: u32 = CLOCK_SPEED / FPS;
 
 #[derive(Debug, Clone)]
 pub enum ExecutionStatus {
@@ -19,14 +14,14 @@ pub enum ExecutionStatus {
     Halt,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Event {
     PlaySound(Sound),
     StopSound(Sound),
     Debug(u8),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Sound {
     UFO,
     Shoot,
@@ -39,131 +34,442 @@ pub enum Sound {
     UFOExplode,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedEvent {
+    pub cycle: u64,
+    pub event: Event,
+}
+
+/// What the CPU of a Midway 8080 board sees: the machine's memory map, and its I/O board
+/// and shift register on the ports. Board events wait here until the emulator timestamps
+/// them at the end of the instruction.
+#[derive(Debug, Clone)]
+pub struct MidwayBus {
+    memory: Memory,
+    board: Box<dyn IoBoard>,
+    shift_register: ShiftRegister,
+    controls: Controls,
+    dip_switches: DipSwitches,
+    events: Vec<Event>,
+}
+
+impl MidwayBus {
+    fn new(machine: &'static Machine, memory: Memory) -> Self {
+        Self {
+            memory,
+            board: machine.board.create(),
+            shift_register: ShiftRegister::new(),
+            controls: Controls::default(),
+            dip_switches: DipSwitches::default(),
+            events: Vec::new(),
+        }
+    }
+}
+
+impl Bus for MidwayBus {
+    fn read(&self, adr: u16) -> u8 {
+        self.memory.read(adr)
+    }
+
+    fn write(&mut self, adr: u16, val: u8) {
+        self.memory.write(adr, val);
+    }
+
+    fn input(&mut self, port: u8) -> Result<u8> {
+        if port == self.board.shift_ports().result {
+            return Ok(self.shift_register.result());
+        }
+
+        self.board.read(port, self.controls, self.dip_switches).ok_or(Error::InvalidReadPort { port })
+    }
+
+    fn output(&mut self, port: u8, val: u8) -> Result<()> {
+        let shift_ports = self.board.shift_ports();
+        if port == shift_ports.amount {
+            self.shift_register.write_amount(val);
+        } else if port == shift_ports.data {
+            self.shift_register.write_data(val);
+        } else if !self.board.write(port, val, &mut self.events) {
+            return Err(Error::InvalidWritePort { port });
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Emulator {
-    cpu: CPU,
-    shift_lo: u8,
-    shift_hi: u8,
-    shift_offset: u8,
-    input_1: u8,
-    input_2: u8,
-    last_port_3: u8,
-    last_port_5: u8,
-    event: Option<Event>,
+    machine: &'static Machine,
+    cpu: CPU<MidwayBus>,
+    cycles: u64,
+    frame: u64,
+    frame_cycles: u32,
+    mid_frame_done: bool,
+    events: VecDeque<TimedEvent>,
 }
 
 impl Emulator {
-    pub fn new(program: &[u8]) -> Self {
+    /// Creates an emulator for the machine detected from the ROM, with the default DIP switches.
+    pub fn new(rom: &[u8]) -> Result<Self> {
+        Self::with_dip_switches(rom, DipSwitches::default())
+    }
+
+    pub fn with_dip_switches(rom: &[u8], dip_switches: DipSwitches) -> Result<Self> {
+        let machine = Machine::detect(rom)?;
+        Self::with_machine(machine, rom, dip_switches)
+    }
+
+    pub fn with_machine(machine: &'static Machine, rom: &[u8], dip_switches: DipSwitches) -> Result<Self> {
+        if rom.len() != machine.rom_size() {
+            return Err(Error::InvalidRomSize { size: rom.len() });
+        }
+
+        let memory = Memory::with_layout(rom, machine.rom, machine.ram);
+        let mut emulator = Self::with_memory(machine, memory);
+        emulator.set_dip_switches(dip_switches);
+        Ok(emulator)
+    }
+
+    /// Creates a Midway board running a short test program, padded to fill the ROM.
+    #[cfg(test)]
+    pub(crate) fn with_program(program: &[u8]) -> Self {
+        Self::with_memory(&crate::machine::MIDWAY, CPU::new(program).unwrap().bus)
+    }
+
+    fn with_memory(machine: &'static Machine, memory: Memory) -> Self {
         Self {
-            cpu: CPU::new(program),
-            shift_lo: 0,
-            shift_hi: 0,
-            shift_offset: 0,
-            input_1: 1,
-            input_2: 0,
-            last_port_3: 0,
-            last_port_5: 0,
-            event: None,
+            machine,
+            cpu: CPU::with_bus(MidwayBus::new(machine, memory)),
+            cycles: 0,
+            frame: 0,
+            frame_cycles: 0,
+            mid_frame_done: false,
+            events: VecDeque::new(),
         }
     }
 
+    /// Executes one instruction, raising the mid-screen (RST 1) and VBlank (RST 2)
+    /// interrupts once the frame's cycle count reaches them. Executing HLT returns
+    /// `ExecutionStatus::Halt`, and the CPU then idles until the next interrupt.
     pub fn step(&mut self) -> Result<ExecutionStatus> {
         let cycles = self.cpu.step()?;
+        self.cycles += cycles as u64;
+        self.frame_cycles += cycles;
+
+        for event in self.cpu.bus.events.drain(..) {
+            self.events.push_back(TimedEvent { cycle: self.cycles, event });
+        }
+        let halted = matches!(self.cpu.event(), Some(CPUEvent::Halt));
+
+        if !self.mid_frame_done && self.frame_cycles >= CYCLES_PER_FRAME / 2 {
+            self.cpu.interrupt(1);
+            self.mid_frame_done = true;
+        }
+        if self.frame_cycles >= CYCLES_PER_FRAME {
+            self.end_frame();
+        }
 
-        if let Some(event) = self.cpu.event() {
-            match event {
-                CPUEvent::Halt => return Ok(ExecutionStatus::Halt),
-                CPUEvent::PortWrite(port, val) => self.write_port(port, val)?,
-                CPUEvent::PortRead(port) => {
-                    let val = self.read_port(port)?;
-                    self.cpu.port_in(val);
-                }
-            }
+        Ok(if halted { ExecutionStatus::Halt } else { ExecutionStatus::Continue(cycles) })
+    }
+
+    /// Runs until the end of the current video frame and returns the events it produced.
+    pub fn run_frame(&mut self) -> Result<Vec<TimedEvent>> {
+        let frame = self.frame;
+        while self.frame == frame {
+            self.step()?;
         }
 
-        Ok(ExecutionStatus::Continue(cycles))
+        Ok(self.events.drain(..).collect())
+    }
+
+    /// Number of video frames completed since power-on.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    fn end_frame(&mut self) {
+        self.cpu.interrupt(2);
+        self.frame += 1;
+        self.frame_cycles = self.frame_cycles.saturating_sub(CYCLES_PER_FRAME);
+        self.mid_frame_done = false;
     }
 
     pub fn video_ram(&self) -> &[u8] {
-        &self.cpu.memory[0x2400..0x4000]
+        &self.cpu.bus.memory[0x2400..0x4000]
+    }
+
+    pub fn dip_switches(&self) -> DipSwitches {
+        self.cpu.bus.dip_switches
+    }
+
+    /// Takes effect the next time the game reads the switches, which it mostly
+    /// does at power-on.
+    pub fn set_dip_switches(&mut self, dip_switches: DipSwitches) {
+        self.cpu.bus.dip_switches = dip_switches;
     }
 
+    /// Resets the machine to its power-on state. The DIP switches are kept.
     pub fn reset(&mut self) {
         self.cpu.reset();
+        self.cpu.bus.memory.reset_ram();
+        self.cpu.bus.board.reset();
+        self.cpu.bus.shift_register.reset();
+        self.cpu.bus.controls = Controls::default();
+        self.cpu.bus.events.clear();
+        self.cycles = 0;
+        self.frame = 0;
+        self.frame_cycles = 0;
+        self.mid_frame_done = false;
+        self.events.clear();
+    }
+
+    pub fn machine(&self) -> &'static Machine {
+        self.machine
+    }
+
+    pub fn rom_hash(&self) -> u32 {
+        self.cpu.bus.memory.rom_hash()
+    }
+
+    pub fn ram_hash(&self) -> u32 {
+        self.cpu.bus.memory.ram_hash()
+    }
+
+    pub fn video_hash(&self) -> u32 {
+        crc32fast::hash(self.video_ram())
     }
 
     pub fn button_press(&mut self, button: Button) {
-        let mask = button.mask();
-        match button {
-            Button::Coin => self.input_1 &= !mask,
-            Button::Tilt | Button::P2Shoot | Button::P2Left | Button::P2Right => self.input_2 |= mask,
-            _ => self.input_1 |= mask,
-        }
+        self.cpu.bus.controls.set(button, true);
     }
 
     pub fn button_release(&mut self, button: Button) {
-        let mask = button.mask();
-        match button {
-            Button::Coin => self.input_1 |= mask,
-            Button::Tilt | Button::P2Shoot | Button::P2Left | Button::P2Right => self.input_2 &= !mask,
-            _ => self.input_1 &= !mask,
-        }
+        self.cpu.bus.controls.set(button, false);
+    }
+
+    pub fn cpu(&self) -> &CPU<MidwayBus> {
+        &self.cpu
     }
 
-    pub fn cpu_mut(&mut self) -> &mut CPU {
+    pub fn cpu_mut(&mut self) -> &mut CPU<MidwayBus> {
         &mut self.cpu
     }
 
-    pub fn event(&mut self) -> Option<Event> {
-        mem::replace(&mut self.event, None)
-    }
-
-    fn write_port(&mut self, port: u8, val: u8) -> Result<()> {
-        match port {
-            2 => self.shift_offset = val & 0x7,
-            3 => {
-                if val != self.last_port_3 {
-                    check_sound_events!(self.last_port_3, val, self.event,
-                        (0x01, Sound::UFO),
-                        (0x02, Sound::Shoot),
-                        (0x04, Sound::PlayerDie),
-                        (0x08, Sound::InvaderDie)
-                    );
-                    self.last_port_3 = val;
-                }
-            }
-            4 => {
-                self.shift_lo = self.shift_hi;
-                self.shift_hi = val;
-            }
-            5 => {
-                if val != self.last_port_5 {
-                    check_sound_events!(self.last_port_5, val, self.event,
-                        (0x01, Sound::Bomp1),
-                        (0x02, Sound::Bomp2),
-                        (0x04, Sound::Bomp3),
-                        (0x08, Sound::Bomp4),
-                        (0x10, Sound::UFOExplode)
-                    );
-                    self.last_port_5 = val;
-                }
-            }
-            6 => self.event = Some(Event::Debug(val)),
-            _ => return Err(Error::InvalidWritePort { port })
-        }
+    pub fn memory(&self) -> &Memory {
+        &self.cpu.bus.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.cpu.bus.memory
+    }
 
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        let bus = &self.cpu.bus;
+        self.cpu.save_state(&mut writer);
+        bus.memory.save_state(&mut writer);
+        bus.shift_register.save_state(&mut writer);
+        writer.write_u8(bus.dip_switches.bits());
+        writer.write_u16(bus.controls.bits());
+        writer.write_bytes(&bus.board.save_state());
+        writer.write_u64(self.cycles);
+        writer.write_u64(self.frame);
+        writer.write_u32(self.frame_cycles);
+        writer.write_u8(self.mid_frame_done as u8);
+
+        writer.finish(self.rom_hash())
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        let mut reader = StateReader::new(data, self.rom_hash())?;
+        let mut state = self.clone();
+
+        state.cpu.load_state(&mut reader)?;
+        let bus = &mut state.cpu.bus;
+        bus.memory.load_state(&mut reader)?;
+        bus.shift_register.load_state(&mut reader)?;
+        bus.dip_switches = DipSwitches::from_bits(reader.read_u8()?);
+        bus.controls = Controls::from_bits(reader.read_u16()?);
+        bus.board.load_state(reader.read_bytes()?)?;
+        bus.events.clear();
+        state.cycles = reader.read_u64()?;
+        state.frame = reader.read_u64()?;
+        state.frame_cycles = reader.read_u32()?;
+        state.mid_frame_done = reader.read_u8()? != 0;
+        state.events.clear();
+        reader.finish()?;
+
+        *self = state;
         Ok(())
     }
 
-    fn read_port(&mut self, port: u8) -> Result<u8> {
-        Ok(match port {
-            1 => self.input_1,
-            2 => self.input_2,
-            3 => {
-                let shift_val = concat_u16!(self.shift_hi, self.shift_lo);
-                ((shift_val >> (8 - self.shift_offset)) & 0xFF) as u8
-            }
-            _ => return Err(Error::InvalidReadPort { port })
-        })
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn event(&mut self) -> Option<TimedEvent> {
+        self.events.pop_front()
+    }
+
+    pub fn events(&mut self) -> impl Iterator<Item = TimedEvent> + '_ {
+        self.events.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_simultaneous_sound_events() {
+        let mut emulator = Emulator::with_program(&[
+            0x3E, 0x01,     // MVI   A,01h
+            0xD3, 0x03,     // OUT   3
+            0x3E, 0x02,     // MVI   A,02h
+            0xD3, 0x03,     // OUT   3
+        ]);
+
+        for _ in 0..4 {
+            emulator.step().unwrap();
+        }
+
+        let events: Vec<TimedEvent> = emulator.events().collect();
+        assert_eq!(events, [
+            TimedEvent { cycle: 17, event: Event::PlaySound(Sound::UFO) },
+            TimedEvent { cycle: 34, event: Event::StopSound(Sound::UFO) },
+            TimedEvent { cycle: 34, event: Event::PlaySound(Sound::Shoot) },
+        ]);
+        assert_eq!(emulator.event(), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_run_frame() {
+        let mut program = vec![
+            0x31, 0x00, 0x24,   // LXI   SP,2400h
+            0xFB,               // EI
+            0xC3, 0x04, 0x00,   // JMP   0004h
+            0x00,
+        ];
+        for counter in [0x00, 0x01] {
+            program.extend_from_slice(&[
+                0xF5,               // PUSH  PSW
+                0x21, counter, 0x20,// LXI   H,20xxh
+                0x34,               // INR   M
+                0xF1,               // POP   PSW
+                0xFB,               // EI
+                0xC9,               // RET
+            ]);
+        }
+
+        let mut emulator = Emulator::with_program(&program);
+        for _ in 0..3 {
+            emulator.run_frame().unwrap();
+        }
+
+        assert_eq!(emulator.frame(), 3);
+        assert_eq!(emulator.memory()[0x2000], 3);
+        // The last VBlank is raised at the very end of the frame, so its handler runs in the next one
+        assert_eq!(emulator.memory()[0x2001], 2);
+        assert!(emulator.cycles() >= 3 * CYCLES_PER_FRAME as u64);
+        assert!(emulator.cycles() < 4 * CYCLES_PER_FRAME as u64);
+    }
+
+    #[test]
+    fn test_halt_mid_frame() {
+        let mut program = vec![
+            0x31, 0x00, 0x24,   // LXI   SP,2400h
+            0xFB,               // EI
+            0x76,               // HLT
+            0xC3, 0x04, 0x00,   // JMP   0004h
+        ];
+        for counter in [0x00, 0x01] {
+            program.extend_from_slice(&[
+                0x21, counter, 0x20,// LXI   H,20xxh
+                0x34,               // INR   M
+                0xFB,               // EI
+                0xC9,               // RET
+                0x00, 0x00,
+            ]);
+        }
+
+        let mut emulator = Emulator::with_program(&program);
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+        assert!(matches!(emulator.step().unwrap(), ExecutionStatus::Halt));
+        assert!(emulator.cpu().is_halted());
+
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.frame(), 1);
+        assert!(emulator.cycles() >= CYCLES_PER_FRAME as u64);
+        assert!(emulator.cycles() < CYCLES_PER_FRAME as u64 + 8);
+        assert_eq!(emulator.memory()[0x2000], 1);
+        assert_eq!(emulator.memory()[0x2001], 0);
+        // VBlank woke the CPU at the end of the frame
+        assert!(!emulator.cpu().is_halted());
+
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.memory()[0x2000], 2);
+        assert_eq!(emulator.memory()[0x2001], 1);
+        assert!(emulator.cycles() < 2 * CYCLES_PER_FRAME as u64 + 8);
+    }
+
+    #[test]
+    fn test_save_state() {
+        let program = [0x31, 0x00, 0x24, 0x3E, 0x42, 0xF5, 0xC3, 0x03, 0x00];
+        let mut emulator = Emulator::with_program(&program);
+        for _ in 0..4 {
+            emulator.step().unwrap();
+        }
+
+        let state = emulator.save_state();
+        let mut restored = Emulator::with_program(&program);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.cpu().sp(), 0x23FE);
+
+        let mut corrupted = state.clone();
+        corrupted[20] ^= 0xFF;
+        assert!(matches!(restored.load_state(&corrupted), Err(Error::SaveStateChecksumMismatch)));
+
+        let mut other_rom = Emulator::with_program(&[0x00]);
+        assert!(matches!(other_rom.load_state(&state), Err(Error::SaveStateRomMismatch)));
+
+        assert!(matches!(restored.load_state(&state[..10]), Err(Error::InvalidSaveState)));
+    }
+
+    #[test]
+    fn test_extended_rom() {
+        let mut rom = vec![0; crate::machine::PART_II.rom_size()];
+        rom[..3].copy_from_slice(&[0xC3, 0x00, 0x40]); // JMP 4000h
+        rom[0x2000..0x2006].copy_from_slice(&[0x3E, 0x42, 0x32, 0x00, 0x20, 0x76]); // MVI A,42h; STA 2000h; HLT
+
+        let mut emulator = Emulator::with_machine(&crate::machine::PART_II, &rom, DipSwitches::default()).unwrap();
+        for _ in 0..3 {
+            emulator.step().unwrap();
+        }
+        assert_eq!(emulator.memory()[0x2000], 0x42);
+        assert!(emulator.memory().is_rom(0x47FF));
+        // RAM is still mirrored past the extra ROM
+        assert_eq!(emulator.memory()[0x6000], 0x42);
+    }
+
+    #[test]
+    fn test_dip_switches() {
+        // IN 2; STA 2000h; HLT
+        let mut emulator = Emulator::with_program(&[0xDB, 0x02, 0x32, 0x00, 0x20, 0x76]);
+        let dips = DipSwitches { lives: 5, bonus_life_at_1000: true, coin_info: false };
+        emulator.set_dip_switches(dips);
+        emulator.button_press(Button::P2Shoot);
+        for _ in 0..2 {
+            emulator.step().unwrap();
+        }
+        assert_eq!(emulator.memory()[0x2000], 0b1001_1010);
+
+        let state = emulator.save_state();
+        emulator.reset();
+        emulator.set_dip_switches(DipSwitches::default());
+        emulator.load_state(&state).unwrap();
+        assert_eq!(emulator.dip_switches(), dips);
+    }
+}
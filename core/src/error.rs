@@ -5,18 +5,62 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     UnimplementedOpcode { opcode: u8 },
+    StepLimitExceeded { steps: u64 },
     InvalidReadPort { port: u8 },
     InvalidWritePort { port: u8 },
+    InvalidSaveState,
+    UnsupportedSaveStateVersion { version: u16 },
+    SaveStateRomMismatch,
+    SaveStateChecksumMismatch,
+    InvalidMovie,
+    UnsupportedMovieVersion { version: u16 },
+    MovieRomMismatch,
+    MovieDesync { frame: u64 },
+    InvalidOverlay { line: usize },
+    InvalidOverlayMask,
+    InvalidColorProm { size: usize },
+    InvalidRomSize { size: usize },
+    AmbiguousRom { size: usize },
+    MissingRomChip { name: &'static str },
+    BadRomChip { name: &'static str, expected: u32, actual: u32 },
+    InvalidRomArchive,
+    Io { source: std::io::Error },
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::UnimplementedOpcode { opcode } => write!(f, "unimplemented opcode: 0x{:02X}", opcode),
+            Self::StepLimitExceeded { steps } => write!(f, "program didn't finish within {} steps", steps),
             Self::InvalidWritePort { port } => write!(f, "invalid write port: {}", port),
             Self::InvalidReadPort { port } => write!(f, "invalid read port: {}", port),
+            Self::InvalidSaveState => write!(f, "invalid or corrupted save state"),
+            Self::UnsupportedSaveStateVersion { version } => write!(f, "unsupported save state version: {}", version),
+            Self::SaveStateRomMismatch => write!(f, "save state was created with a different ROM"),
+            Self::SaveStateChecksumMismatch => write!(f, "save state checksum mismatch"),
+            Self::InvalidMovie => write!(f, "invalid or corrupted movie"),
+            Self::UnsupportedMovieVersion { version } => write!(f, "unsupported movie version: {}", version),
+            Self::MovieRomMismatch => write!(f, "movie was recorded with a different ROM"),
+            Self::MovieDesync { frame } => write!(f, "movie playback desynced at frame {}", frame),
+            Self::InvalidOverlay { line } => write!(f, "invalid overlay definition on line {}", line),
+            Self::InvalidOverlayMask => write!(f, "overlay mask must be a 224x256 binary PPM image"),
+            Self::InvalidColorProm { size } => write!(f, "color PROM must be 1024 bytes, got {}", size),
+            Self::InvalidRomSize { size } => write!(f, "no supported machine has a ROM of {} bytes", size),
+            Self::AmbiguousRom { size } => write!(f, "unknown ROM set, and several machines have a ROM of {} bytes", size),
+            Self::MissingRomChip { name } => write!(f, "ROM chip {} is missing", name),
+            Self::BadRomChip { name, expected, actual } => {
+                write!(f, "ROM chip {} is a bad dump (CRC32 {:08x}, expected {:08x})", name, actual, expected)
+            }
+            Self::InvalidRomArchive => write!(f, "invalid or corrupted zip archive"),
+            Self::Io { source } => write!(f, "{}", source),
         }
     }
 }
 
 impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(source: std::io::Error) -> Self {
+        Self::Io { source }
+    }
+}
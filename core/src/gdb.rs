@@ -0,0 +1,453 @@
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+
+use crate::{concat_u16, Emulator, Error, ExecutionStatus, Register};
+
+pub const SIGINT: u8 = 2;
+pub const SIGILL: u8 = 4;
+pub const SIGTRAP: u8 = 5;
+pub const SIGSEGV: u8 = 11;
+
+// Register numbering used by the `g`/`p` packets: the eight 8-bit registers
+// in this order, followed by SP and PC as 16-bit little-endian values
+const REGISTERS: [Register; 8] = [
+    Register::A,
+    Register::B,
+    Register::C,
+    Register::D,
+    Register::E,
+    Register::H,
+    Register::L,
+    Register::Flags,
+];
+const SP_REGISTER: usize = 8;
+const PC_REGISTER: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Detach,
+    Kill,
+}
+
+/// GDB remote serial protocol stub serving a single client.
+///
+/// The host drives execution: after `wait` returns `Action::Continue` it
+/// runs the emulator, checking `should_break` before every instruction and
+/// `interrupt_requested` periodically, and calls `stop` when either fires.
+pub struct GdbStub {
+    stream: TcpStream,
+    breakpoints: BTreeSet<u16>,
+    no_ack: bool,
+}
+
+impl GdbStub {
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let (stream, _) = listener.accept()?;
+        Self::new(stream)
+    }
+
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+
+        Ok(Self {
+            stream,
+            breakpoints: BTreeSet::new(),
+            no_ack: false,
+        })
+    }
+
+    pub fn should_break(&self, emulator: &Emulator) -> bool {
+        self.breakpoints.contains(&emulator.cpu().pc())
+    }
+
+    pub fn interrupt_requested(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0];
+        let result = self.stream.peek(&mut buf);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(_) if buf[0] == 0x03 => {
+                self.stream.read_exact(&mut buf)?;
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn stop(&mut self, emulator: &mut Emulator, signal: u8) -> io::Result<Action> {
+        self.send(&format!("S{:02x}", signal))?;
+        self.wait(emulator)
+    }
+
+    pub fn wait(&mut self, emulator: &mut Emulator) -> io::Result<Action> {
+        loop {
+            let packet = self.receive()?;
+            let (command, args) = packet.split_at(packet.len().min(1));
+
+            let reply = match command {
+                "?" => format!("S{:02x}", SIGTRAP),
+                "g" => read_registers(emulator),
+                "G" => write_registers(emulator, args),
+                "p" => match parse_hex(args) {
+                    Some(n) => read_register(emulator, n as usize),
+                    None => "E01".to_owned(),
+                },
+                "P" => match args.split_once('=') {
+                    Some((n, val)) => write_register(emulator, n, val),
+                    None => "E01".to_owned(),
+                },
+                "m" => read_memory(emulator, args),
+                "M" => write_memory(emulator, args),
+                "c" => {
+                    if let Some(adr) = parse_hex(args) {
+                        emulator.cpu_mut().set_pc(adr);
+                    }
+                    return Ok(Action::Continue);
+                }
+                "s" => {
+                    if let Some(adr) = parse_hex(args) {
+                        emulator.cpu_mut().set_pc(adr);
+                    }
+                    let signal = match emulator.step() {
+                        Ok(ExecutionStatus::Continue(_)) | Ok(ExecutionStatus::Halt) => SIGTRAP,
+                        Err(e) => {
+                            self.console(&format!("{}\n", e))?;
+                            fault_signal(&e)
+                        }
+                    };
+                    format!("S{:02x}", signal)
+                }
+                "Z" | "z" => self.update_breakpoint(command == "Z", args),
+                "D" => {
+                    self.send("OK")?;
+                    return Ok(Action::Detach);
+                }
+                "k" => return Ok(Action::Kill),
+                "H" => "OK".to_owned(),
+                "q" | "Q" => match packet.as_str() {
+                    "QStartNoAckMode" => {
+                        self.send("OK")?;
+                        self.no_ack = true;
+                        continue;
+                    }
+                    "qAttached" => "1".to_owned(),
+                    p if p.starts_with("qSupported") => "PacketSize=1000;QStartNoAckMode+".to_owned(),
+                    _ => String::new(),
+                },
+                _ => String::new(),
+            };
+
+            self.send(&reply)?;
+        }
+    }
+
+    fn update_breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+
+        // Only software breakpoints are supported
+        if fields.next() != Some("0") {
+            return String::new();
+        }
+
+        match fields.next().and_then(parse_hex) {
+            Some(adr) => {
+                if insert {
+                    self.breakpoints.insert(adr);
+                } else {
+                    self.breakpoints.remove(&adr);
+                }
+                "OK".to_owned()
+            }
+            None => "E01".to_owned(),
+        }
+    }
+
+    fn receive(&mut self) -> io::Result<String> {
+        loop {
+            let mut byte = [0];
+
+            // Skip acks and anything else outside of a packet
+            loop {
+                self.stream.read_exact(&mut byte)?;
+                if byte[0] == b'$' { break; }
+            }
+
+            let mut data = Vec::new();
+            loop {
+                self.stream.read_exact(&mut byte)?;
+                if byte[0] == b'#' { break; }
+                data.push(byte[0]);
+            }
+
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|s| u8::from_str_radix(s, 16).ok());
+            let valid = expected == Some(checksum_of(&data));
+
+            if !self.no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+
+            if valid {
+                return Ok(String::from_utf8_lossy(&data).into_owned());
+            }
+        }
+    }
+
+    /// Prints `message` on the debugger's console.
+    fn console(&mut self, message: &str) -> io::Result<()> {
+        let hex: String = message.bytes().map(|b| format!("{:02x}", b)).collect();
+        self.send(&format!("O{}", hex))
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        loop {
+            write!(self.stream, "${}#{:02x}", data, checksum_of(data.as_bytes()))?;
+            self.stream.flush()?;
+
+            if self.no_ack {
+                return Ok(());
+            }
+
+            let mut ack = [0];
+            self.stream.read_exact(&mut ack)?;
+            if ack[0] == b'+' {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Signal to report for an emulation error that stopped the CPU.
+pub fn fault_signal(error: &Error) -> u8 {
+    match error {
+        Error::UnimplementedOpcode { .. } => SIGILL,
+        _ => SIGSEGV,
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+fn parse_hex(s: &str) -> Option<u16> {
+    u16::from_str_radix(s, 16).ok()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
+
+fn read_register(emulator: &Emulator, n: usize) -> String {
+    let cpu = emulator.cpu();
+
+    match n {
+        0..=7 => format!("{:02x}", cpu.register(REGISTERS[n])),
+        SP_REGISTER => format!("{:02x}{:02x}", cpu.sp() as u8, cpu.sp() >> 8),
+        PC_REGISTER => format!("{:02x}{:02x}", cpu.pc() as u8, cpu.pc() >> 8),
+        _ => "E01".to_owned(),
+    }
+}
+
+fn read_registers(emulator: &Emulator) -> String {
+    (0..=PC_REGISTER).map(|n| read_register(emulator, n)).collect()
+}
+
+fn write_register(emulator: &mut Emulator, n: &str, val: &str) -> String {
+    let (Some(n), Some(bytes)) = (parse_hex(n), decode_hex(val)) else {
+        return "E01".to_owned();
+    };
+
+    let cpu = emulator.cpu_mut();
+
+    match (n as usize, bytes.as_slice()) {
+        (n @ 0..=7, [val]) => cpu.set_register(REGISTERS[n], *val),
+        (SP_REGISTER, [lo, hi]) => cpu.set_sp(concat_u16!(*hi, *lo)),
+        (PC_REGISTER, [lo, hi]) => cpu.set_pc(concat_u16!(*hi, *lo)),
+        _ => return "E01".to_owned(),
+    }
+
+    "OK".to_owned()
+}
+
+fn write_registers(emulator: &mut Emulator, data: &str) -> String {
+    let Some(bytes) = decode_hex(data) else {
+        return "E01".to_owned();
+    };
+
+    if bytes.len() < 12 {
+        return "E01".to_owned();
+    }
+
+    let cpu = emulator.cpu_mut();
+    for (register, val) in REGISTERS.iter().zip(&bytes) {
+        cpu.set_register(*register, *val);
+    }
+    cpu.set_sp(concat_u16!(bytes[9], bytes[8]));
+    cpu.set_pc(concat_u16!(bytes[11], bytes[10]));
+
+    "OK".to_owned()
+}
+
+fn parse_range(args: &str) -> Option<(u16, u16)> {
+    let (adr, len) = args.split_once(',')?;
+    Some((parse_hex(adr)?, parse_hex(len)?))
+}
+
+fn read_memory(emulator: &Emulator, args: &str) -> String {
+    let Some((adr, len)) = parse_range(args) else {
+        return "E01".to_owned();
+    };
+
+    let memory = emulator.memory();
+    (0..len).map(|i| format!("{:02x}", memory[adr.wrapping_add(i)])).collect()
+}
+
+fn write_memory(emulator: &mut Emulator, args: &str) -> String {
+    let Some((range, data)) = args.split_once(':') else {
+        return "E01".to_owned();
+    };
+
+    let (Some((adr, len)), Some(bytes)) = (parse_range(range), decode_hex(data)) else {
+        return "E01".to_owned();
+    };
+
+    let memory = emulator.memory_mut();
+    if bytes.len() != len as usize || (0..len).any(|i| memory.is_rom(adr.wrapping_add(i))) {
+        return "E01".to_owned();
+    }
+
+    memory.load(adr, &bytes);
+    "OK".to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use super::*;
+
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn request(&mut self, data: &str) -> String {
+            write!(self.stream, "${}#{:02x}", data, checksum_of(data.as_bytes())).unwrap();
+
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0], b'+');
+
+            self.receive()
+        }
+
+        fn receive(&mut self) -> String {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0], b'$');
+
+            let mut reply = Vec::new();
+            loop {
+                self.stream.read_exact(&mut byte).unwrap();
+                if byte[0] == b'#' { break; }
+                reply.push(byte[0]);
+            }
+
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum).unwrap();
+            self.stream.write_all(b"+").unwrap();
+
+            String::from_utf8(reply).unwrap()
+        }
+    }
+
+    fn connect() -> (GdbStub, Client) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+            stream.set_nodelay(true).unwrap();
+            stream
+        });
+        let (stream, _) = listener.accept().unwrap();
+
+        (GdbStub::new(stream).unwrap(), Client { stream: client.join().unwrap() })
+    }
+
+    #[test]
+    fn test_session() {
+        // LXI SP,2400h; MVI A,42h; NOP; JMP 0005h
+        let mut emulator = Emulator::with_program(&[0x31, 0x00, 0x24, 0x3E, 0x42, 0x00, 0xC3, 0x05, 0x00]);
+        let (mut stub, mut client) = connect();
+
+        let session = thread::spawn(move || {
+            assert_eq!(client.request("?"), "S05");
+            assert_eq!(client.request("s"), "S05");
+            assert_eq!(client.request("s"), "S05");
+            assert_eq!(client.request("g"), "420000000000000200240500");
+            assert_eq!(client.request("m3,2"), "3e42");
+            assert_eq!(client.request("M2000,2:beef"), "OK");
+            assert_eq!(client.request("M0,1:00"), "E01");
+            assert_eq!(client.request("P1=7f"), "OK");
+            assert_eq!(client.request("p1"), "7f");
+            assert_eq!(client.request("Z0,6,1"), "OK");
+            client.stream.write_all(b"$c#63").unwrap();
+            client
+        });
+
+        assert_eq!(stub.wait(&mut emulator).unwrap(), Action::Continue);
+        assert_eq!(emulator.memory()[0x2000..0x2002], [0xBE, 0xEF]);
+
+        while !stub.should_break(&emulator) {
+            emulator.step().unwrap();
+        }
+        assert_eq!(emulator.cpu().pc(), 0x0006);
+
+        let mut client = session.join().unwrap();
+        let mut ack = [0];
+        client.stream.read_exact(&mut ack).unwrap();
+
+        let session = thread::spawn(move || {
+            let mut reply = [0; 7];
+            client.stream.read_exact(&mut reply).unwrap();
+            assert_eq!(&reply, b"$S05#b8");
+            client.stream.write_all(b"+").unwrap();
+            assert_eq!(client.request("D"), "OK");
+        });
+
+        assert_eq!(stub.stop(&mut emulator, SIGTRAP).unwrap(), Action::Detach);
+        session.join().unwrap();
+    }
+
+    #[test]
+    fn test_step_fault() {
+        let mut emulator = Emulator::with_program(&[0xDB, 0x07]);  // IN 7
+        let (mut stub, mut client) = connect();
+
+        let session = thread::spawn(move || {
+            let message: String = "invalid read port: 7\n".bytes().map(|b| format!("{:02x}", b)).collect();
+            assert_eq!(client.request("s"), format!("O{}", message));
+            assert_eq!(client.receive(), "S0b");
+            assert_eq!(client.request("D"), "OK");
+        });
+
+        assert_eq!(stub.wait(&mut emulator).unwrap(), Action::Detach);
+        session.join().unwrap();
+        assert_eq!(fault_signal(&Error::UnimplementedOpcode { opcode: 0x08 }), SIGILL);
+    }
+}
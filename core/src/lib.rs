@@ -1,15 +1,34 @@
 mod cpu;
 mod memory;
+mod bus;
 mod error;
 mod macros;
 mod emulator;
+mod cpm;
+mod state;
+mod rewind;
+mod movie;
+mod dip;
+mod shift_register;
+pub mod board;
+pub mod disasm;
+pub mod gdb;
+pub mod machine;
+pub mod rom;
+pub mod video;
 
 pub use error::{Result, Error};
-pub use cpu::{CPU, Event as CPUEvent};
-pub use emulator::{Emulator, ExecutionStatus, Event as EmulatorEvent, Sound};
+pub use cpu::{CPU, Event as CPUEvent, Register, CARRY_FLAG, PARITY_FLAG, AUX_CARRY_FLAG, ZERO_FLAG, SIGN_FLAG};
+pub use emulator::{Emulator, MidwayBus, ExecutionStatus, Event as EmulatorEvent, Sound, TimedEvent, CLOCK_SPEED, CYCLES_PER_FRAME, FPS};
 pub use memory::Memory;
+pub use bus::Bus;
+pub use cpm::CpmMachine;
+pub use rewind::RewindBuffer;
+pub use dip::DipSwitches;
+pub use shift_register::ShiftRegister;
+pub use movie::{Movie, MovieInput, MoviePlayer, MovieRecorder, StartState};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Button {
     P1Start,
     P2Start,
@@ -24,20 +43,45 @@ pub enum Button {
 }
 
 impl Button {
-    fn mask(&self) -> u8 {
+    pub const ALL: [Button; 10] = [
+        Self::P1Start,
+        Self::P2Start,
+        Self::P1Shoot,
+        Self::P2Shoot,
+        Self::P1Left,
+        Self::P2Left,
+        Self::P1Right,
+        Self::P2Right,
+        Self::Tilt,
+        Self::Coin,
+    ];
+
+    pub fn index(&self) -> u8 {
+        Self::ALL.iter().position(|b| b == self).unwrap() as u8
+    }
+
+    pub fn from_index(index: u8) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+
+    pub fn name(&self) -> &'static str {
         match self {
-            Self::Coin => 0b0000_0001,
-            Self::P2Start => 0b0000_0010,
-            Self::P1Start => 0b0000_0100,
-            Self::P1Shoot => 0b0001_0000,
-            Self::P1Left => 0b0010_0000,
-            Self::P1Right => 0b0100_0000,
-            Self::Tilt => 0b0000_0100,
-            Self::P2Shoot => 0b0001_0000,
-            Self::P2Left => 0b0010_0000,
-            Self::P2Right => 0b0100_0000,
+            Self::P1Start => "p1start",
+            Self::P2Start => "p2start",
+            Self::P1Shoot => "p1shoot",
+            Self::P2Shoot => "p2shoot",
+            Self::P1Left => "p1left",
+            Self::P2Left => "p2left",
+            Self::P1Right => "p1right",
+            Self::P2Right => "p2right",
+            Self::Tilt => "tilt",
+            Self::Coin => "coin",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|button| button.name().eq_ignore_ascii_case(name))
+    }
 }
 
 pub fn even_parity(mut n: u8) -> bool {
@@ -57,10 +101,19 @@ mod test {
 
     #[test]
     fn test_even_parity() {
-        assert_eq!(super::even_parity(0b1101), false);
-        assert_eq!(super::even_parity(0b0101_1101), false);
-        assert_eq!(super::even_parity(0b1001), true);
-        assert_eq!(super::even_parity(0b1100_1111), true);
+        assert!(!super::even_parity(0b1101));
+        assert!(!super::even_parity(0b0101_1101));
+        assert!(super::even_parity(0b1001));
+        assert!(super::even_parity(0b1100_1111));
+    }
+
+    #[test]
+    fn test_button_names() {
+        for button in Button::ALL {
+            assert_eq!(Button::from_name(button.name()), Some(button));
+        }
+        assert_eq!(Button::from_name("P1Shoot"), Some(Button::P1Shoot));
+        assert_eq!(Button::from_name("fire"), None);
     }
 
     #[test]
@@ -0,0 +1,158 @@
+use crate::{Result, Error};
+use crate::board::Board;
+use crate::rom;
+
+/// A block of program ROM mapped at `address`. A machine's ROM image holds its regions
+/// back to back, in the order they're listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomRegion {
+    pub address: u16,
+    pub size: usize,
+}
+
+/// RAM mapped at `start` and mirrored across every address no ROM region covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RamLayout {
+    pub start: u16,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coloring {
+    /// Monochrome monitor behind colored cellophane, shown with the named built-in overlay.
+    Overlay(&'static str),
+    /// Colors looked up for each 8x8 block from a 1K color PROM. See `Overlay::from_color_prom`.
+    ColorProm,
+}
+
+/// Hardware configuration of a game running on a Midway 8080 board or one of its clones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Machine {
+    pub name: &'static str,
+    pub title: &'static str,
+    pub rom: &'static [RomRegion],
+    pub ram: RamLayout,
+    pub board: Board,
+    pub coloring: Coloring,
+}
+
+impl Machine {
+    /// Size of a ROM image holding all of the machine's regions.
+    pub fn rom_size(&self) -> usize {
+        self.rom.iter().map(|region| region.size).sum()
+    }
+
+    pub fn from_name(name: &str) -> Option<&'static Machine> {
+        MACHINES.iter().copied().find(|machine| machine.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Picks the machine of a known ROM set, or else the only one whose ROM size matches.
+    /// Unknown ROMs of a size several machines share have to be given a machine explicitly.
+    pub fn detect(rom: &[u8]) -> Result<&'static Machine> {
+        if let Some(set) = rom::identify(rom) {
+            return Ok(set.machine);
+        }
+
+        let mut candidates = MACHINES.iter().copied().filter(|machine| machine.rom_size() == rom.len());
+        match (candidates.next(), candidates.next()) {
+            (Some(machine), None) => Ok(machine),
+            (Some(_), Some(_)) => Err(Error::AmbiguousRom { size: rom.len() }),
+            (None, _) => Err(Error::InvalidRomSize { size: rom.len() }),
+        }
+    }
+}
+
+const RAM: RamLayout = RamLayout { start: 0x2000, size: 0x2000 };
+
+const MAIN_ROM: &[RomRegion] = &[RomRegion { address: 0x0000, size: 0x2000 }];
+
+/// Part II, Deluxe and Balloon Bomber add a 2K ROM above the mirrored RAM.
+const EXTENDED_ROM: &[RomRegion] = &[
+    RomRegion { address: 0x0000, size: 0x2000 },
+    RomRegion { address: 0x4000, size: 0x0800 },
+];
+
+/// The original Space Invaders board.
+pub const MIDWAY: Machine = Machine {
+    name: "midway",
+    title: "Space Invaders",
+    rom: MAIN_ROM,
+    ram: RAM,
+    board: Board::Invaders,
+    coloring: Coloring::Overlay("Cellophane"),
+};
+
+pub const PART_II: Machine = Machine {
+    name: "part2",
+    title: "Space Invaders Part II",
+    rom: EXTENDED_ROM,
+    ram: RAM,
+    board: Board::InvadersII,
+    coloring: Coloring::ColorProm,
+};
+
+pub const DELUXE: Machine = Machine {
+    name: "deluxe",
+    title: "Space Invaders Deluxe",
+    rom: EXTENDED_ROM,
+    ram: RAM,
+    board: Board::InvadersII,
+    coloring: Coloring::Overlay("Cellophane"),
+};
+
+pub const LUNAR_RESCUE: Machine = Machine {
+    name: "lrescue",
+    title: "Lunar Rescue",
+    rom: &[RomRegion { address: 0x0000, size: 0x2000 }, RomRegion { address: 0x4000, size: 0x1000 }],
+    ram: RAM,
+    board: Board::Taito,
+    coloring: Coloring::ColorProm,
+};
+
+pub const BALLOON_BOMBER: Machine = Machine {
+    name: "ballbomb",
+    title: "Balloon Bomber",
+    rom: EXTENDED_ROM,
+    ram: RAM,
+    board: Board::Taito,
+    coloring: Coloring::ColorProm,
+};
+
+pub const SPACE_ATTACK: Machine = Machine {
+    name: "spaceatt",
+    title: "Space Attack",
+    rom: MAIN_ROM,
+    ram: RAM,
+    board: Board::Invaders,
+    coloring: Coloring::Overlay("Cellophane"),
+};
+
+pub const BOOT_HILL: Machine = Machine {
+    name: "boothill",
+    title: "Boot Hill",
+    rom: MAIN_ROM,
+    ram: RAM,
+    board: Board::BootHill,
+    coloring: Coloring::Overlay("Black and white"),
+};
+
+pub const GUN_FIGHT: Machine = Machine {
+    name: "gunfight",
+    title: "Gun Fight",
+    rom: &[RomRegion { address: 0x0000, size: 0x1000 }],
+    ram: RAM,
+    board: Board::GunFight,
+    coloring: Coloring::Overlay("Black and white"),
+};
+
+/// Every machine profile.
+pub const MACHINES: &[&Machine] = &[
+    &MIDWAY,
+    &PART_II,
+    &DELUXE,
+    &LUNAR_RESCUE,
+    &BALLOON_BOMBER,
+    &SPACE_ATTACK,
+    &BOOT_HILL,
+    &GUN_FIGHT,
+];
@@ -1,47 +1,113 @@
 use std::ops::{Index, IndexMut, Range};
+use crate::{Result, Error};
+use crate::machine::{self, RamLayout, RomRegion};
+use crate::state::{StateReader, StateWriter};
 
 #[derive(Debug, Clone)]
 pub struct Memory {
-    rom: [u8; 0x2000],
-    ram: [u8; 0x2000],
+    rom: Vec<u8>,
+    /// Address, offset into `rom` and size of each ROM region.
+    regions: Vec<(usize, usize, usize)>,
+    ram_start: usize,
+    ram: Vec<u8>,
 }
 
 impl Memory {
+    /// Memory map of the original Midway board.
     pub fn new(rom: [u8; 0x2000]) -> Self {
+        Self::with_layout(&rom, machine::MIDWAY.rom, machine::MIDWAY.ram)
+    }
+
+    /// Maps `rom` into the given regions in order. It must hold exactly as many bytes as the
+    /// regions cover.
+    pub fn with_layout(rom: &[u8], regions: &[RomRegion], ram: RamLayout) -> Self {
+        let mut offset = 0;
+        let regions = regions
+            .iter()
+            .map(|region| {
+                offset += region.size;
+                (region.address as usize, offset - region.size, region.size)
+            })
+            .collect();
+        assert_eq!(offset, rom.len(), "ROM doesn't fill its regions");
+
         Self {
-            rom,
-            ram: [0; 0x2000],
+            rom: rom.to_vec(),
+            regions,
+            ram_start: ram.start as usize,
+            ram: vec![0; ram.size],
+        }
+    }
+
+    pub fn flat() -> Self {
+        Self::with_layout(&[], &[], RamLayout { start: 0, size: 0x10000 })
+    }
+
+    pub fn load(&mut self, adr: u16, data: &[u8]) {
+        for (i, val) in data.iter().enumerate() {
+            self[adr.wrapping_add(i as u16)] = *val;
         }
     }
 
+    pub fn is_rom(&self, adr: u16) -> bool {
+        self.rom_offset(adr as usize).is_some()
+    }
+
+    fn rom_offset(&self, adr: usize) -> Option<usize> {
+        self.regions
+            .iter()
+            .find(|&&(address, _, size)| adr >= address && adr < address + size)
+            .map(|&(address, offset, _)| offset + adr - address)
+    }
+
+    fn ram_offset(&self, adr: usize) -> usize {
+        adr.wrapping_sub(self.ram_start) % self.ram.len()
+    }
+
     pub fn reset_ram(&mut self) {
         self.ram.fill(0);
     }
+
+    pub fn rom_hash(&self) -> u32 {
+        crc32fast::hash(&self.rom)
+    }
+
+    pub fn ram_hash(&self) -> u32 {
+        crc32fast::hash(&self.ram)
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        let ram = reader.read_bytes()?;
+        if ram.len() != self.ram.len() {
+            return Err(Error::InvalidSaveState);
+        }
+
+        self.ram.copy_from_slice(ram);
+        Ok(())
+    }
 }
 
 impl Index<u16> for Memory {
     type Output = u8;
 
     fn index(&self, index: u16) -> &Self::Output {
-        let rom_len = self.rom.len();
-        let index = index as usize;
-
-        if index < rom_len {
-            &self.rom[index]
-        } else {
-            &self.ram[(index - rom_len) % self.ram.len()]
+        match self.rom_offset(index as usize) {
+            Some(offset) => &self.rom[offset],
+            None => &self.ram[self.ram_offset(index as usize)],
         }
     }
 }
 
 impl IndexMut<u16> for Memory {
     fn index_mut(&mut self, index: u16) -> &mut Self::Output {
-        let rom_len = self.rom.len();
-        let index = index as usize;
+        if self.is_rom(index) { panic!("cannot write to ROM"); }
 
-        if index < rom_len { panic!("cannot write to ROM"); }
-
-        &mut self.ram[(index - rom_len) % self.ram.len()]
+        let offset = self.ram_offset(index as usize);
+        &mut self.ram[offset]
     }
 }
 
@@ -49,14 +115,14 @@ impl Index<Range<u16>> for Memory {
     type Output = [u8];
 
     fn index(&self, range: Range<u16>) -> &Self::Output {
-        let start = range.start as usize;
-        let end = range.end as usize;
-        let rom_len = self.rom.len();
-
-        if start >= rom_len {
-            &self.ram[start - rom_len..end - rom_len]
-        } else {
-            &self.rom[start..end]
+        let len = (range.end - range.start) as usize;
+
+        match self.rom_offset(range.start as usize) {
+            Some(offset) => &self.rom[offset..offset + len],
+            None => {
+                let offset = self.ram_offset(range.start as usize);
+                &self.ram[offset..offset + len]
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,307 @@
+use crate::{Result, Error, Emulator, Button, DipSwitches};
+use crate::state::{StateReader, StateWriter};
+
+const MAGIC: &[u8; 4] = b"SIMV";
+const VERSION: u16 = 2;
+
+/// Number of frames between RAM checksums recorded in a movie.
+pub const CHECKSUM_INTERVAL: u64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartState {
+    PowerOn,
+    SaveState(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieInput {
+    pub frame: u64,
+    pub button: Button,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    rom_hash: u32,
+    dip_switches: DipSwitches,
+    start: StartState,
+    inputs: Vec<MovieInput>,
+    checksums: Vec<(u64, u32)>,
+    length: u64,
+}
+
+impl Movie {
+    pub fn rom_hash(&self) -> u32 {
+        self.rom_hash
+    }
+
+    pub fn dip_switches(&self) -> DipSwitches {
+        self.dip_switches
+    }
+
+    pub fn start(&self) -> &StartState {
+        &self.start
+    }
+
+    pub fn inputs(&self) -> &[MovieInput] {
+        &self.inputs
+    }
+
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        writer.write_u32(self.rom_hash);
+        writer.write_u8(self.dip_switches.bits());
+        match &self.start {
+            StartState::PowerOn => writer.write_u8(0),
+            StartState::SaveState(state) => {
+                writer.write_u8(1);
+                writer.write_bytes(state);
+            }
+        }
+
+        writer.write_u32(self.inputs.len() as u32);
+        for input in &self.inputs {
+            writer.write_u64(input.frame);
+            writer.write_u8(input.button.index());
+            writer.write_u8(input.pressed as u8);
+        }
+
+        writer.write_u32(self.checksums.len() as u32);
+        for &(frame, checksum) in &self.checksums {
+            writer.write_u64(frame);
+            writer.write_u32(checksum);
+        }
+
+        writer.write_u64(self.length);
+
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend(writer.into_bytes());
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        if data.len() < 6 || &data[..4] != MAGIC {
+            return Err(Error::InvalidMovie);
+        }
+
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != VERSION {
+            return Err(Error::UnsupportedMovieVersion { version });
+        }
+
+        Self::read(StateReader::from_bytes(&data[6..])).map_err(|_| Error::InvalidMovie)
+    }
+
+    fn read(mut reader: StateReader) -> Result<Self> {
+        let rom_hash = reader.read_u32()?;
+        let dip_switches = DipSwitches::from_bits(reader.read_u8()?);
+        let start = match reader.read_u8()? {
+            0 => StartState::PowerOn,
+            1 => StartState::SaveState(reader.read_bytes()?.to_vec()),
+            _ => return Err(Error::InvalidMovie),
+        };
+
+        let inputs = (0..reader.read_u32()?)
+            .map(|_| {
+                let frame = reader.read_u64()?;
+                let button = Button::from_index(reader.read_u8()?).ok_or(Error::InvalidMovie)?;
+                let pressed = reader.read_u8()? != 0;
+                Ok(MovieInput { frame, button, pressed })
+            })
+            .collect::<Result<_>>()?;
+
+        let checksums = (0..reader.read_u32()?)
+            .map(|_| Ok((reader.read_u64()?, reader.read_u32()?)))
+            .collect::<Result<_>>()?;
+
+        let length = reader.read_u64()?;
+        reader.finish()?;
+
+        Ok(Self { rom_hash, dip_switches, start, inputs, checksums, length })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    /// Starts recording from the emulator's current state. With
+    /// `StartState::PowerOn` the emulator is reset first.
+    pub fn new(emulator: &mut Emulator, power_on: bool) -> Self {
+        let start = if power_on {
+            emulator.reset();
+            StartState::PowerOn
+        } else {
+            StartState::SaveState(emulator.save_state())
+        };
+
+        Self {
+            movie: Movie {
+                rom_hash: emulator.rom_hash(),
+                dip_switches: emulator.dip_switches(),
+                start,
+                inputs: Vec::new(),
+                checksums: Vec::new(),
+                length: 0,
+            },
+        }
+    }
+
+    pub fn record_input(&mut self, frame: u64, button: Button, pressed: bool) {
+        self.movie.inputs.push(MovieInput { frame, button, pressed });
+    }
+
+    /// Must be called after the emulator finishes each frame.
+    pub fn end_frame(&mut self, frame: u64, emulator: &Emulator) {
+        if frame.is_multiple_of(CHECKSUM_INTERVAL) {
+            self.movie.checksums.push((frame, emulator.ram_hash()));
+        }
+        self.movie.length = frame + 1;
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MoviePlayer {
+    movie: Movie,
+    next_input: usize,
+    next_checksum: usize,
+}
+
+impl MoviePlayer {
+    /// Puts the emulator in the movie's starting state and DIP switch settings.
+    /// Frame numbers passed to the player afterwards count from 0.
+    pub fn new(movie: Movie, emulator: &mut Emulator) -> Result<Self> {
+        if movie.rom_hash != emulator.rom_hash() {
+            return Err(Error::MovieRomMismatch);
+        }
+
+        emulator.set_dip_switches(movie.dip_switches);
+        match &movie.start {
+            StartState::PowerOn => emulator.reset(),
+            StartState::SaveState(state) => emulator.load_state(state)?,
+        }
+
+        Ok(Self { movie, next_input: 0, next_checksum: 0 })
+    }
+
+    /// Must be called before the emulator runs each frame.
+    pub fn start_frame(&mut self, frame: u64, emulator: &mut Emulator) {
+        while let Some(input) = self.movie.inputs.get(self.next_input) {
+            if input.frame > frame {
+                break;
+            }
+
+            if input.pressed {
+                emulator.button_press(input.button);
+            } else {
+                emulator.button_release(input.button);
+            }
+            self.next_input += 1;
+        }
+    }
+
+    /// Must be called after the emulator finishes each frame. Fails with
+    /// `Error::MovieDesync` at the first frame whose RAM doesn't match.
+    pub fn end_frame(&mut self, frame: u64, emulator: &Emulator) -> Result<()> {
+        while let Some(&(checksum_frame, checksum)) = self.movie.checksums.get(self.next_checksum) {
+            if checksum_frame > frame {
+                break;
+            }
+
+            self.next_checksum += 1;
+            if checksum_frame == frame && checksum != emulator.ram_hash() {
+                return Err(Error::MovieDesync { frame });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_finished(&self, frame: u64) -> bool {
+        frame >= self.movie.length
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Accumulates input port 1 into 2000h:
+    // LXI SP,2400h; IN 1; MOV B,A; LDA 2000h; ADD B; STA 2000h; JMP 0003h
+    const PROGRAM: [u8; 16] = [
+        0x31, 0x00, 0x24, 0xDB, 0x01, 0x47, 0x3A, 0x00, 0x20, 0x80, 0x32, 0x00, 0x20, 0xC3, 0x03, 0x00,
+    ];
+
+    fn run_frame(emulator: &mut Emulator) {
+        emulator.run_frame().unwrap();
+    }
+
+    fn record() -> (Movie, Vec<u8>) {
+        let mut emulator = Emulator::with_program(&PROGRAM);
+        let mut recorder = MovieRecorder::new(&mut emulator, true);
+
+        for frame in 0..200 {
+            if frame % 50 == 10 {
+                emulator.button_press(Button::P1Shoot);
+                recorder.record_input(frame, Button::P1Shoot, true);
+            } else if frame % 50 == 20 {
+                emulator.button_release(Button::P1Shoot);
+                recorder.record_input(frame, Button::P1Shoot, false);
+            }
+
+            run_frame(&mut emulator);
+            recorder.end_frame(frame, &emulator);
+        }
+
+        (recorder.finish(), emulator.save_state())
+    }
+
+    #[test]
+    fn test_playback() {
+        let (movie, final_state) = record();
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.length(), 200);
+
+        let mut emulator = Emulator::with_program(&PROGRAM);
+        let mut player = MoviePlayer::new(movie, &mut emulator).unwrap();
+
+        let mut frame = 0;
+        while !player.is_finished(frame) {
+            player.start_frame(frame, &mut emulator);
+            run_frame(&mut emulator);
+            player.end_frame(frame, &emulator).unwrap();
+            frame += 1;
+        }
+
+        assert_eq!(emulator.save_state(), final_state);
+    }
+
+    #[test]
+    fn test_desync() {
+        let (mut movie, _) = record();
+        movie.inputs.remove(0);
+
+        let mut emulator = Emulator::with_program(&PROGRAM);
+        let mut player = MoviePlayer::new(movie, &mut emulator).unwrap();
+
+        let error = (0..200).find_map(|frame| {
+            player.start_frame(frame, &mut emulator);
+            run_frame(&mut emulator);
+            player.end_frame(frame, &emulator).err()
+        });
+
+        assert!(matches!(error, Some(Error::MovieDesync { frame: 60 })));
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::VecDeque;
+use crate::{Result, Emulator};
+
+/// Ring buffer of emulator snapshots for stepping gameplay backwards.
+///
+/// Only the newest snapshot is kept in full. Every older one is stored as a
+/// delta that rebuilds it from the snapshot after it, so the oldest entries
+/// can be dropped without touching the rest once the byte budget is hit.
+#[derive(Debug, Clone)]
+pub struct RewindBuffer {
+    current: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    size: usize,
+    capacity: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            current: None,
+            deltas: VecDeque::new(),
+            size: 0,
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, emulator: &Emulator) {
+        let state = emulator.save_state();
+
+        if let Some(previous) = self.current.take() {
+            if previous.len() == state.len() {
+                let delta = encode_delta(&state, &previous);
+                self.size += delta.len();
+                self.deltas.push_back(delta);
+            } else {
+                self.clear();
+            }
+        }
+
+        self.current = Some(state);
+
+        while self.size > self.capacity {
+            match self.deltas.pop_front() {
+                Some(delta) => self.size -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Restores the newest snapshot and removes it from the buffer. Returns
+    /// `false` if there was nothing left to rewind to.
+    pub fn rewind(&mut self, emulator: &mut Emulator) -> Result<bool> {
+        let Some(state) = self.current.take() else {
+            return Ok(false);
+        };
+
+        if let Some(delta) = self.deltas.pop_back() {
+            self.size -= delta.len();
+            self.current = Some(apply_delta(&state, &delta));
+        }
+
+        emulator.load_state(&state)?;
+        Ok(true)
+    }
+
+    pub fn len(&self) -> usize {
+        self.current.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+        self.size = 0;
+    }
+}
+
+// Deltas are the XOR of two equally sized snapshots, stored as a sequence of
+// (zero run length, literal length, literal bytes) records with LEB128
+// encoded lengths.
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = from.iter().zip(to).map(|(a, b)| a ^ b).collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < xor.len() {
+        let zeros = xor[i..].iter().take_while(|&&b| b == 0).count();
+        i += zeros;
+
+        if i == xor.len() {
+            break;
+        }
+
+        let literals = xor[i..].iter().take_while(|&&b| b != 0).count();
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, literals);
+        out.extend_from_slice(&xor[i..i + literals]);
+        i += literals;
+    }
+
+    out
+}
+
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut out = from.to_vec();
+    let mut pos = 0;
+    let mut i = 0;
+
+    while i < delta.len() {
+        pos += read_varint(delta, &mut i);
+        let literals = read_varint(delta, &mut i);
+
+        for (dst, src) in out[pos..pos + literals].iter_mut().zip(&delta[i..i + literals]) {
+            *dst ^= src;
+        }
+
+        pos += literals;
+        i += literals;
+    }
+
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        out.push((val as u8) | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn read_varint(data: &[u8], i: &mut usize) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = data[*i];
+        *i += 1;
+        val |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return val;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // LXI SP,2400h; INR A; STA 2000h; JMP 0003h
+    const PROGRAM: [u8; 10] = [0x31, 0x00, 0x24, 0x3C, 0x32, 0x00, 0x20, 0xC3, 0x03, 0x00];
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let from: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let mut to = from.clone();
+        to[0] = 7;
+        to[500..520].fill(0xAA);
+        to[999] = 1;
+
+        let delta = encode_delta(&from, &to);
+        assert!(delta.len() < 40);
+        assert_eq!(apply_delta(&from, &delta), to);
+        assert!(encode_delta(&from, &from).is_empty());
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut emulator = Emulator::with_program(&PROGRAM);
+        let mut buffer = RewindBuffer::new(usize::MAX);
+        let mut states = Vec::new();
+
+        for _ in 0..10 {
+            for _ in 0..4 {
+                emulator.step().unwrap();
+            }
+            buffer.push(&emulator);
+            states.push(emulator.save_state());
+        }
+
+        assert_eq!(buffer.len(), 10);
+
+        for state in states.iter().rev() {
+            assert!(buffer.rewind(&mut emulator).unwrap());
+            assert_eq!(&emulator.save_state(), state);
+        }
+
+        assert!(!buffer.rewind(&mut emulator).unwrap());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut emulator = Emulator::with_program(&PROGRAM);
+        let mut buffer = RewindBuffer::new(64);
+
+        for _ in 0..100 {
+            for _ in 0..4 {
+                emulator.step().unwrap();
+            }
+            buffer.push(&emulator);
+        }
+
+        assert!(buffer.size <= 64);
+        assert!(buffer.len() < 100);
+    }
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use sha1::{Digest, Sha1};
+use crate::{Error, Result};
+use crate::machine::{self, Machine, MACHINES};
+
+/// Name of a single-file ROM image inside a directory or archive.
+const IMAGE_NAME: &str = "invaders";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chip {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    pub crc32: u32,
+    pub sha1: &'static str,
+}
+
+impl Chip {
+    pub fn verify(&self, data: &[u8]) -> Result<()> {
+        let crc32 = crc32fast::hash(data);
+        let sha1: String = Sha1::digest(data).iter().map(|b| format!("{:02x}", b)).collect();
+
+        if data.len() != self.size || crc32 != self.crc32 || sha1 != self.sha1 {
+            return Err(Error::BadRomChip { name: self.name, expected: self.crc32, actual: crc32 });
+        }
+
+        Ok(())
+    }
+}
+
+/// Program ROMs of the original Midway board, using MAME's file names.
+pub const INVADERS_CHIPS: [Chip; 4] = [
+    Chip { name: "invaders.h", offset: 0x0000, size: 0x800, crc32: 0x734F5AD8, sha1: "ff6200af4c9110d8181249cbcef1a8a40fa40b7f" },
+    Chip { name: "invaders.g", offset: 0x0800, size: 0x800, crc32: 0x6BFACA4A, sha1: "16f48649b531bdef8c2d1446c429b5f414524350" },
+    Chip { name: "invaders.f", offset: 0x1000, size: 0x800, crc32: 0x0CCEAD96, sha1: "537aef03468f63c5b9e11dd61e253f7ae17d9743" },
+    Chip { name: "invaders.e", offset: 0x1800, size: 0x800, crc32: 0x14E538B0, sha1: "1d6ca0c99f9df71e2990b610deb9d7da0125e2d8" },
+];
+
+/// A known ROM set and the machine configuration it runs with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomSet {
+    /// MAME's short name for the set.
+    pub name: &'static str,
+    pub title: &'static str,
+    pub manufacturer: &'static str,
+    pub year: u16,
+    pub chips: &'static [Chip],
+    pub machine: &'static Machine,
+    /// Name of the built-in overlay matching the cabinet's artwork.
+    pub overlay: &'static str,
+}
+
+impl RomSet {
+    /// Whether every chip of the set appears at its place in the program.
+    pub fn matches(&self, rom: &[u8]) -> bool {
+        rom.len() == self.machine.rom_size()
+            && self.chips.iter().all(|chip| crc32fast::hash(&rom[chip.offset..chip.offset + chip.size]) == chip.crc32)
+    }
+}
+
+impl fmt::Display for RomSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}, {})", self.title, self.manufacturer, self.year)
+    }
+}
+
+/// Known sets. Only dumps whose checksums have been checked against a real board belong
+/// here; new entries take their chip names, sizes and hashes from MAME's driver. Part II,
+/// Deluxe and the other Midway 8080 games have no entries yet, so `Machine::detect` falls
+/// back to the ROM size for them.
+pub const ROM_SETS: &[RomSet] = &[
+    RomSet {
+        name: "invaders",
+        title: "Space Invaders",
+        manufacturer: "Midway",
+        year: 1978,
+        chips: &INVADERS_CHIPS,
+        machine: &machine::MIDWAY,
+        overlay: "Cellophane",
+    },
+];
+
+pub fn identify(rom: &[u8]) -> Option<&'static RomSet> {
+    ROM_SETS.iter().find(|set| set.matches(rom))
+}
+
+/// Loads the program from an image file, a zip archive, or a directory holding
+/// either an image named `invaders` or the individual chips.
+pub fn load(path: &Path) -> Result<Vec<u8>> {
+    if path.is_dir() {
+        load_dir(path)
+    } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip")) {
+        load_zip(path)
+    } else {
+        load_image(&fs::read(path)?)
+    }
+}
+
+/// Accepts an image of the size of any machine's ROM.
+pub fn load_image(data: &[u8]) -> Result<Vec<u8>> {
+    if MACHINES.iter().all(|machine| machine.rom_size() != data.len()) {
+        return Err(Error::InvalidRomSize { size: data.len() });
+    }
+
+    Ok(data.to_vec())
+}
+
+pub fn load_dir(path: &Path) -> Result<Vec<u8>> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() && metadata.len() <= max_rom_size() {
+            files.push((entry.file_name().to_string_lossy().into_owned(), fs::read(entry.path())?));
+        }
+    }
+
+    from_files(&files)
+}
+
+pub fn load_zip(path: &Path) -> Result<Vec<u8>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(|_| Error::InvalidRomArchive)?;
+    let mut files = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|_| Error::InvalidRomArchive)?;
+        if !entry.is_file() || entry.size() > max_rom_size() {
+            continue;
+        }
+
+        let name = entry.name().rsplit('/').next().unwrap_or_default().to_owned();
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data).map_err(|_| Error::InvalidRomArchive)?;
+        files.push((name, data));
+    }
+
+    from_files(&files)
+}
+
+/// Uses the image if there is one, or else assembles the program from the chips of the
+/// first known set that any of the files belong to.
+fn from_files(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    if let Some((_, image)) = files.iter().find(|(file, _)| file.eq_ignore_ascii_case(IMAGE_NAME)) {
+        return load_image(image);
+    }
+
+    let set = ROM_SETS
+        .iter()
+        .find(|set| set.chips.iter().any(|chip| find_chip(files, chip).is_some()))
+        .unwrap_or(&ROM_SETS[0]);
+
+    let mut rom = vec![0; set.machine.rom_size()];
+    for chip in set.chips {
+        let data = find_chip(files, chip).ok_or(Error::MissingRomChip { name: chip.name })?;
+        chip.verify(data)?;
+        rom[chip.offset..chip.offset + chip.size].copy_from_slice(data);
+    }
+
+    Ok(rom)
+}
+
+/// Looks a chip up by name first, and by checksum for renamed dumps.
+fn find_chip<'a>(files: &'a [(String, Vec<u8>)], chip: &Chip) -> Option<&'a [u8]> {
+    files
+        .iter()
+        .find(|(file, _)| file.eq_ignore_ascii_case(chip.name))
+        .or_else(|| files.iter().find(|(_, data)| crc32fast::hash(data) == chip.crc32))
+        .map(|(_, data)| data.as_slice())
+}
+
+/// Files larger than this are skipped when looking for an image or chips.
+fn max_rom_size() -> u64 {
+    MACHINES.iter().map(|machine| machine.rom_size()).max().unwrap_or_default() as u64
+}
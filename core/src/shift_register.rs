@@ -0,0 +1,104 @@
+use crate::{concat_u16, Result, Error};
+use crate::state::{StateReader, StateWriter};
+
+/// The dedicated shifter of Midway 8080 boards (an MB14241 on later ones), which the CPU
+/// uses to draw sprites at any horizontal pixel offset. Each data write pushes a byte
+/// into the top of a 16-bit register, and the result is the 8 bits starting `amount`
+/// bits below the top.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShiftRegister {
+    lo: u8,
+    hi: u8,
+    amount: u8,
+}
+
+impl ShiftRegister {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only the low 3 bits are wired.
+    pub fn write_amount(&mut self, val: u8) {
+        self.amount = val & 0x07;
+    }
+
+    pub fn write_data(&mut self, val: u8) {
+        self.lo = self.hi;
+        self.hi = val;
+    }
+
+    pub fn result(&self) -> u8 {
+        let val = concat_u16!(self.hi, self.lo);
+        (val >> (8 - self.amount)) as u8
+    }
+
+    /// Clears the register and amount, as at power-on.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.lo);
+        writer.write_u8(self.hi);
+        writer.write_u8(self.amount);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        let (lo, hi, amount) = (reader.read_u8()?, reader.read_u8()?, reader.read_u8()?);
+        if amount > 0x07 {
+            return Err(Error::InvalidSaveState);
+        }
+
+        *self = Self { lo, hi, amount };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_offsets() {
+        let mut shifter = ShiftRegister::new();
+        shifter.write_data(0b1100_1010);
+        shifter.write_data(0b0110_1001);
+
+        // 0110_1001_1100_1010, read 8 bits at a time starting `amount` bits from the top
+        let expected = [
+            0b0110_1001, 0b1101_0011, 0b1010_0111, 0b0100_1110,
+            0b1001_1100, 0b0011_1001, 0b0111_0010, 0b1110_0101,
+        ];
+        for (amount, expected) in expected.into_iter().enumerate() {
+            shifter.write_amount(amount as u8);
+            assert_eq!(shifter.result(), expected, "amount {}", amount);
+        }
+
+        shifter.write_amount(0x0A);
+        assert_eq!(shifter.result(), expected[2]);
+    }
+
+    #[test]
+    fn test_reset_and_state() {
+        let mut shifter = ShiftRegister::new();
+        shifter.write_data(0xFF);
+        shifter.write_data(0x0F);
+        shifter.write_amount(3);
+
+        let mut writer = StateWriter::new();
+        shifter.save_state(&mut writer);
+        let data = writer.into_bytes();
+
+        let mut restored = ShiftRegister::new();
+        restored.load_state(&mut StateReader::from_bytes(&data)).unwrap();
+        assert_eq!(restored, shifter);
+        assert_eq!(restored.result(), 0b0111_1111);
+
+        restored.reset();
+        assert_eq!(restored, ShiftRegister::new());
+        assert_eq!(restored.result(), 0);
+
+        let mut reader = StateReader::from_bytes(&[0, 0, 8]);
+        assert!(matches!(restored.load_state(&mut reader), Err(Error::InvalidSaveState)));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{Result, Error};
+
+const MAGIC: &[u8; 4] = b"SIST";
+const VERSION: u16 = 5;
+
+const HEADER_LEN: usize = 14;
+const CHECKSUM_LEN: usize = 4;
+
+#[derive(Debug, Default)]
+pub(crate) struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    pub fn write_u16(&mut self, val: u16) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, val: u32) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, val: u64) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /// Wraps the payload written so far with a header and checksum.
+    pub fn finish(self, rom_hash: u32) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.buf.len() + CHECKSUM_LEN);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&rom_hash.to_le_bytes());
+        out.extend_from_slice(&(self.buf.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.buf);
+        out.extend_from_slice(&crc32fast::hash(&self.buf).to_le_bytes());
+        out
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Validates the header and checksum of a save state and returns a
+    /// reader over its payload.
+    pub fn new(data: &'a [u8], rom_hash: u32) -> Result<Self> {
+        if data.len() < HEADER_LEN + CHECKSUM_LEN || &data[..4] != MAGIC {
+            return Err(Error::InvalidSaveState);
+        }
+
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != VERSION {
+            return Err(Error::UnsupportedSaveStateVersion { version });
+        }
+
+        if u32::from_le_bytes(data[6..10].try_into().unwrap()) != rom_hash {
+            return Err(Error::SaveStateRomMismatch);
+        }
+
+        let len = u32::from_le_bytes(data[10..14].try_into().unwrap()) as usize;
+        if data.len() != HEADER_LEN + len + CHECKSUM_LEN {
+            return Err(Error::InvalidSaveState);
+        }
+
+        let payload = &data[HEADER_LEN..HEADER_LEN + len];
+        let checksum = u32::from_le_bytes(data[HEADER_LEN + len..].try_into().unwrap());
+        if crc32fast::hash(payload) != checksum {
+            return Err(Error::SaveStateChecksumMismatch);
+        }
+
+        Ok(Self { data: payload })
+    }
+
+    pub fn from_bytes(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn finish(self) -> Result<()> {
+        if self.data.is_empty() { Ok(()) } else { Err(Error::InvalidSaveState) }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(Error::InvalidSaveState);
+        }
+
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+}
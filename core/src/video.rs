@@ -0,0 +1,538 @@
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use crate::{Error, Result};
+
+pub const WIDTH: usize = 224;
+pub const HEIGHT: usize = 256;
+pub const COLOR_PROM_SIZE: usize = 0x400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb(0x00, 0x00, 0x00);
+    pub const WHITE: Rgb = Rgb(0xFF, 0xFF, 0xFF);
+    pub const RED: Rgb = Rgb(0xFF, 0x00, 0x00);
+    pub const GREEN: Rgb = Rgb(0x00, 0xFF, 0x00);
+
+    pub fn to_rgb565(self) -> u16 {
+        ((self.0 as u16 >> 3) << 11) | ((self.1 as u16 >> 2) << 5) | (self.2 as u16 >> 3)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 3 bytes per pixel, in R, G, B order.
+    Rgb24,
+    /// 4 bytes per pixel, in R, G, B, A order.
+    Rgba8888,
+    /// 2 bytes per pixel, little-endian 5-6-5.
+    Rgb565,
+    /// 1 byte per pixel, indexing into the overlay's palette.
+    Indexed8,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Self::Rgb24 => 3,
+            Self::Rgba8888 => 4,
+            Self::Rgb565 => 2,
+            Self::Indexed8 => 1,
+        }
+    }
+
+    pub fn pitch(&self) -> usize {
+        WIDTH * self.bytes_per_pixel()
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.pitch() * HEIGHT
+    }
+}
+
+const BUILTIN_OVERLAYS: [&str; 3] = [
+    include_str!("../assets/overlays/cellophane.overlay"),
+    include_str!("../assets/overlays/bw.overlay"),
+    include_str!("../assets/overlays/color.overlay"),
+];
+
+/// Colors applied to lit pixels depending on their position on the upright screen,
+/// emulating the cellophane or printed overlays placed in front of the monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlay {
+    name: String,
+    palette: Vec<Rgb>,
+    map: Vec<u8>,
+}
+
+impl Overlay {
+    pub fn monochrome(name: &str, color: Rgb) -> Self {
+        Self { name: name.to_owned(), palette: vec![Rgb::BLACK, color], map: vec![1; WIDTH * HEIGHT] }
+    }
+
+    /// The overlays shipped with the emulator, starting with the original cellophane one.
+    pub fn builtin() -> Vec<Self> {
+        BUILTIN_OVERLAYS.iter().map(|text| Self::parse(text).unwrap()).collect()
+    }
+
+    /// Parses an overlay definition. Each line holds one directive, later rectangles drawing
+    /// over earlier ones, and `#` starts a comment:
+    ///
+    /// ```text
+    /// name <name>
+    /// background <rrggbb>
+    /// rect <x> <y> <width> <height> <rrggbb>
+    /// ```
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut overlay = Self::monochrome("Unnamed", Rgb::WHITE);
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            let invalid = || Error::InvalidOverlay { line: i + 1 };
+            let (directive, args) = line.split_once(' ').unwrap_or((line, ""));
+            let args: Vec<&str> = args.split_whitespace().collect();
+
+            match directive {
+                "" => {}
+                "name" => overlay.name = line[4..].trim().to_owned(),
+                "background" => {
+                    let [color] = args[..] else { return Err(invalid()) };
+                    let color = parse_color(color).ok_or_else(invalid)?;
+                    overlay.palette[1] = color;
+                }
+                "rect" => {
+                    let [x, y, width, height, color] = args[..] else { return Err(invalid()) };
+                    let [x, y, width, height] = [x, y, width, height].map(|n| n.parse::<usize>().ok());
+                    let (Some(x), Some(y), Some(width), Some(height)) = (x, y, width, height) else { return Err(invalid()) };
+                    if x + width > WIDTH || y + height > HEIGHT {
+                        return Err(invalid());
+                    }
+
+                    let index = overlay.color_index(parse_color(color).ok_or_else(invalid)?).ok_or_else(invalid)?;
+                    for row in y..y + height {
+                        overlay.map[row * WIDTH + x..row * WIDTH + x + width].fill(index);
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(overlay)
+    }
+
+    /// Builds an overlay from a binary PPM (P6) image of the upright screen, where each
+    /// pixel gives the color of the lit pixels underneath it.
+    pub fn from_mask(name: &str, ppm: &[u8]) -> Result<Self> {
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        while fields.len() < 4 {
+            while ppm.get(pos).ok_or(Error::InvalidOverlayMask)?.is_ascii_whitespace() {
+                pos += 1;
+            }
+            if ppm[pos] == b'#' {
+                while ppm.get(pos).ok_or(Error::InvalidOverlayMask)? != &b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+
+            let start = pos;
+            while ppm.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            fields.push(&ppm[start..pos]);
+        }
+
+        let pixels = ppm.get(pos + 1..).ok_or(Error::InvalidOverlayMask)?;
+        if fields[..] != [&b"P6"[..], b"224", b"256", b"255"] || pixels.len() != WIDTH * HEIGHT * 3 {
+            return Err(Error::InvalidOverlayMask);
+        }
+
+        let mut overlay = Self { name: name.to_owned(), palette: vec![Rgb::BLACK], map: Vec::with_capacity(WIDTH * HEIGHT) };
+        for pixel in pixels.chunks_exact(3) {
+            let index = overlay.color_index(Rgb(pixel[0], pixel[1], pixel[2])).ok_or(Error::InvalidOverlayMask)?;
+            overlay.map.push(index);
+        }
+
+        Ok(overlay)
+    }
+
+    /// Builds an overlay from the color PROM of a Part II board, which gives a 3-bit color
+    /// for each 8x8 block of the unrotated screen, with red in bit 0, blue in bit 1 and
+    /// green in bit 2.
+    pub fn from_color_prom(name: &str, prom: &[u8]) -> Result<Self> {
+        if prom.len() != COLOR_PROM_SIZE {
+            return Err(Error::InvalidColorProm { size: prom.len() });
+        }
+
+        let level = |color: u8, bit: u8| if color & bit != 0 { 0xFF } else { 0 };
+        let palette = (0..8).map(|i| Rgb(level(i, 0x01), level(i, 0x04), level(i, 0x02))).collect();
+        let map = (0..WIDTH * HEIGHT)
+            .map(|i| {
+                let (x, y) = (i % WIDTH, i / WIDTH);
+                prom[(x / 8) * 32 + (HEIGHT - 1 - y) / 8] & 0x07
+            })
+            .collect();
+
+        Ok(Self { name: name.to_owned(), palette, map })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Colors referenced by `PixelFormat::Indexed8` images. Index 0 is always black.
+    pub fn palette(&self) -> &[Rgb] {
+        &self.palette
+    }
+
+    /// Palette index of the color of a lit pixel at an upright screen position.
+    pub fn index_at(&self, x: usize, y: usize) -> u8 {
+        self.map[y * WIDTH + x]
+    }
+
+    fn color_index(&mut self, color: Rgb) -> Option<u8> {
+        match self.palette.iter().position(|&c| c == color) {
+            Some(index) => Some(index as u8),
+            None if self.palette.len() < 256 => {
+                self.palette.push(color);
+                Some(self.palette.len() as u8 - 1)
+            }
+            None => None,
+        }
+    }
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Self::parse(BUILTIN_OVERLAYS[0]).unwrap()
+    }
+}
+
+fn parse_color(hex: &str) -> Option<Rgb> {
+    let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)?;
+    Some(Rgb((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+/// Renders `video_ram` as an upright, row-major image into `out`, which must hold
+/// `format.buffer_size()` bytes. Returns whether any pixel changed.
+///
+/// The monitor is mounted rotated, so each 32 bytes of video RAM hold one screen column
+/// drawn from bottom to top, least significant bit first.
+pub fn render(video_ram: &[u8], overlay: &Overlay, format: PixelFormat, out: &mut [u8]) -> bool {
+    assert_eq!(out.len(), format.buffer_size(), "output buffer has the wrong size");
+
+    let bpp = format.bytes_per_pixel();
+    let mut pixel = [0; 4];
+    let mut update = false;
+
+    for (i, byte) in video_ram.iter().enumerate().take(WIDTH * HEIGHT / 8) {
+        let x = i / (HEIGHT / 8);
+
+        for bit in 0..8 {
+            let y = HEIGHT - 1 - ((i * 8) % HEIGHT + bit);
+            let index = if byte & (1 << bit) == 0 { 0 } else { overlay.index_at(x, y) };
+            let color = overlay.palette[index as usize];
+
+            match format {
+                PixelFormat::Rgb24 => pixel[..3].copy_from_slice(&[color.0, color.1, color.2]),
+                PixelFormat::Rgba8888 => pixel = [color.0, color.1, color.2, 0xFF],
+                PixelFormat::Rgb565 => pixel[..2].copy_from_slice(&color.to_rgb565().to_le_bytes()),
+                PixelFormat::Indexed8 => pixel[0] = index,
+            }
+
+            let offset = (y * WIDTH + x) * bpp;
+            let dest = &mut out[offset..offset + bpp];
+            if dest != &pixel[..bpp] {
+                dest.copy_from_slice(&pixel[..bpp]);
+                update = true;
+            }
+        }
+    }
+
+    update
+}
+
+/// Encodes the screen as an indexed PNG using the overlay's palette, scaled up `scale` times.
+pub fn encode_png(video_ram: &[u8], overlay: &Overlay, scale: usize) -> Vec<u8> {
+    let mut image = vec![0; PixelFormat::Indexed8.buffer_size()];
+    render(video_ram, overlay, PixelFormat::Indexed8, &mut image);
+
+    let scale = scale.max(1);
+    let mut scaled = Vec::with_capacity(image.len() * scale * scale);
+    for row in image.chunks_exact(WIDTH) {
+        let line: Vec<u8> = row.iter().flat_map(|&index| std::iter::repeat_n(index, scale)).collect();
+        for _ in 0..scale {
+            scaled.extend_from_slice(&line);
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, (WIDTH * scale) as u32, (HEIGHT * scale) as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(overlay.palette.iter().flat_map(|c| [c.0, c.1, c.2]).collect::<Vec<u8>>());
+
+    let mut writer = encoder.write_header().expect("writing to memory cannot fail");
+    writer.write_image_data(&scaled).expect("image data matches the header");
+    writer.finish().expect("writing to memory cannot fail");
+
+    out
+}
+
+/// Browsers slow down GIF frames shorter than this, in hundredths of a second.
+const GIF_MIN_DELAY: u64 = 2;
+
+/// Writes an animated GIF of consecutive frames. Each frame only stores the rectangle that
+/// changed since the previous one, with unchanged pixels left transparent, and frames are
+/// merged so no delay is shorter than browsers can play back.
+pub struct GifRecorder<W: Write> {
+    encoder: gif::Encoder<W>,
+    overlay: Overlay,
+    transparent: Option<u8>,
+    written: Option<Vec<u8>>,
+    pending: Option<(Vec<u8>, u64)>,
+    frames: u64,
+}
+
+impl<W: Write> GifRecorder<W> {
+    pub fn new(writer: W, overlay: &Overlay) -> io::Result<Self> {
+        let mut palette: Vec<u8> = overlay.palette.iter().flat_map(|c| [c.0, c.1, c.2]).collect();
+        let transparent = (overlay.palette.len() < 256).then_some(overlay.palette.len() as u8);
+        if transparent.is_some() {
+            palette.extend_from_slice(&[0, 0, 0]);
+        }
+
+        let mut encoder = gif::Encoder::new(writer, WIDTH as u16, HEIGHT as u16, &palette).map_err(io::Error::other)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+
+        Ok(Self { encoder, overlay: overlay.clone(), transparent, written: None, pending: None, frames: 0 })
+    }
+
+    /// Adds the next emulated frame.
+    pub fn push_frame(&mut self, video_ram: &[u8]) -> io::Result<()> {
+        let mut image = vec![0; PixelFormat::Indexed8.buffer_size()];
+        render(video_ram, &self.overlay, PixelFormat::Indexed8, &mut image);
+
+        let frame = self.frames;
+        self.frames += 1;
+
+        match self.pending.take() {
+            Some((pending, start)) if pending == image => self.pending = Some((pending, start)),
+            Some((_, start)) if gif_time(frame) - gif_time(start) < GIF_MIN_DELAY => self.pending = Some((image, start)),
+            Some((pending, start)) => {
+                self.write_frame(pending, gif_time(frame) - gif_time(start))?;
+                self.pending = Some((image, frame));
+            }
+            None => self.pending = Some((image, frame)),
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some((pending, start)) = self.pending.take() {
+            let delay = (gif_time(self.frames) - gif_time(start)).max(GIF_MIN_DELAY);
+            self.write_frame(pending, delay)?;
+        }
+
+        self.encoder.into_inner()
+    }
+
+    fn write_frame(&mut self, image: Vec<u8>, delay: u64) -> io::Result<()> {
+        let (left, top, right, bottom) = match &self.written {
+            Some(written) => {
+                let changed = |i: usize| image[i] != written[i];
+                let rows: Vec<usize> = (0..HEIGHT).filter(|&y| (0..WIDTH).any(|x| changed(y * WIDTH + x))).collect();
+                let columns: Vec<usize> = (0..WIDTH).filter(|&x| rows.iter().any(|&y| changed(y * WIDTH + x))).collect();
+                match (rows.first(), rows.last(), columns.first(), columns.last()) {
+                    (Some(&top), Some(&bottom), Some(&left), Some(&right)) => (left, top, right, bottom),
+                    // Only the timing changed, so extend the previous frame with a 1x1 no-op
+                    _ => (0, 0, 0, 0),
+                }
+            }
+            None => (0, 0, WIDTH - 1, HEIGHT - 1),
+        };
+
+        let mut buffer = Vec::with_capacity((right - left + 1) * (bottom - top + 1));
+        for y in top..=bottom {
+            for x in left..=right {
+                let i = y * WIDTH + x;
+                buffer.push(match (&self.written, self.transparent) {
+                    (Some(written), Some(transparent)) if written[i] == image[i] => transparent,
+                    _ => image[i],
+                });
+            }
+        }
+
+        let frame = gif::Frame {
+            delay: delay.min(u16::MAX as u64) as u16,
+            transparent: self.written.as_ref().and(self.transparent),
+            left: left as u16,
+            top: top as u16,
+            width: (right - left + 1) as u16,
+            height: (bottom - top + 1) as u16,
+            buffer: Cow::Owned(buffer),
+            ..gif::Frame::default()
+        };
+        self.encoder.write_frame(&frame).map_err(io::Error::other)?;
+        self.written = Some(image);
+
+        Ok(())
+    }
+}
+
+/// Time of a frame's start in hundredths of a second.
+fn gif_time(frame: u64) -> u64 {
+    frame * 100 / crate::FPS as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_orientation() {
+        let mut video_ram = [0u8; WIDTH * HEIGHT / 8];
+        video_ram[0] = 0b0000_0001;     // bottom-left corner
+        video_ram[31] = 0b1000_0000;    // top-left corner
+        video_ram[223 * 32 + 31] = 0b1000_0000; // top-right corner
+
+        let overlay = Overlay::default();
+        let mut image = vec![0; PixelFormat::Indexed8.buffer_size()];
+        assert!(render(&video_ram, &overlay, PixelFormat::Indexed8, &mut image));
+        assert!(!render(&video_ram, &overlay, PixelFormat::Indexed8, &mut image));
+
+        let lit: Vec<(usize, usize)> = (0..image.len())
+            .filter(|&i| image[i] != 0)
+            .map(|i| (i % WIDTH, i / WIDTH))
+            .collect();
+        assert_eq!(lit, [(0, 0), (223, 0), (0, 255)]);
+        assert_eq!(image[255 * WIDTH], 1);
+    }
+
+    #[test]
+    fn test_pixel_formats() {
+        let mut video_ram = [0u8; WIDTH * HEIGHT / 8];
+        video_ram[20 * 32 + 25] = 0b0000_0001;  // x 20, y 55: inside the red band
+        let offset = 55 * WIDTH + 20;
+
+        let mut rgb24 = vec![0; PixelFormat::Rgb24.buffer_size()];
+        render(&video_ram, &Overlay::default(), PixelFormat::Rgb24, &mut rgb24);
+        assert_eq!(rgb24[offset * 3..offset * 3 + 3], [0xFF, 0x00, 0x00]);
+
+        let mut rgba = vec![0; PixelFormat::Rgba8888.buffer_size()];
+        render(&video_ram, &Overlay::default(), PixelFormat::Rgba8888, &mut rgba);
+        assert_eq!(rgba[offset * 4..offset * 4 + 4], [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(rgba[0..4], [0x00, 0x00, 0x00, 0xFF]);
+
+        let mut rgb565 = vec![0; PixelFormat::Rgb565.buffer_size()];
+        render(&video_ram, &Overlay::default(), PixelFormat::Rgb565, &mut rgb565);
+        assert_eq!(rgb565[offset * 2..offset * 2 + 2], 0xF800u16.to_le_bytes());
+    }
+
+    #[test]
+    fn test_builtin_overlays() {
+        let overlays = Overlay::builtin();
+        assert_eq!(overlays.iter().map(Overlay::name).collect::<Vec<_>>(), ["Cellophane", "Black and white", "Color bands"]);
+
+        let cellophane = &overlays[0];
+        let color = |x, y| cellophane.palette()[cellophane.index_at(x, y) as usize];
+        assert_eq!(color(100, 10), Rgb::WHITE);
+        assert_eq!(color(100, 40), Rgb::RED);
+        assert_eq!(color(100, 200), Rgb::GREEN);
+        assert_eq!(color(10, 250), Rgb::WHITE);
+        assert_eq!(color(20, 250), Rgb::GREEN);
+        assert!(overlays[1].palette().iter().all(|&c| c == Rgb::BLACK || c == Rgb::WHITE));
+    }
+
+    #[test]
+    fn test_parse_overlay() {
+        let overlay = Overlay::parse("name Test # comment\nbackground 808080\n\nrect 0 0 2 1 0000ff").unwrap();
+        assert_eq!(overlay.name(), "Test");
+        assert_eq!(overlay.palette(), [Rgb::BLACK, Rgb(0x80, 0x80, 0x80), Rgb(0, 0, 0xFF)]);
+        assert_eq!([overlay.index_at(0, 0), overlay.index_at(1, 0), overlay.index_at(2, 0)], [2, 2, 1]);
+
+        assert!(matches!(Overlay::parse("rect 0 0 225 1 ffffff"), Err(Error::InvalidOverlay { line: 1 })));
+        assert!(matches!(Overlay::parse("\nbackground red"), Err(Error::InvalidOverlay { line: 2 })));
+        assert!(matches!(Overlay::parse("circle 1 2 3"), Err(Error::InvalidOverlay { line: 1 })));
+    }
+
+    #[test]
+    fn test_overlay_mask() {
+        let mut ppm = b"P6\n# mask\n224 256\n255\n".to_vec();
+        for i in 0..WIDTH * HEIGHT {
+            ppm.extend_from_slice(if i < WIDTH { &[0, 0, 0xFF] } else { &[0xFF, 0xFF, 0xFF] });
+        }
+
+        let overlay = Overlay::from_mask("Mask", &ppm).unwrap();
+        assert_eq!(overlay.palette()[overlay.index_at(5, 0) as usize], Rgb(0, 0, 0xFF));
+        assert_eq!(overlay.palette()[overlay.index_at(5, 1) as usize], Rgb::WHITE);
+
+        assert!(matches!(Overlay::from_mask("Mask", &ppm[..ppm.len() - 1]), Err(Error::InvalidOverlayMask)));
+        assert!(matches!(Overlay::from_mask("Mask", b"P6 10 10 255\n"), Err(Error::InvalidOverlayMask)));
+    }
+
+    #[test]
+    fn test_color_prom() {
+        let mut prom = vec![0; COLOR_PROM_SIZE];
+        prom[0] = 0x01;
+        prom[27 * 32 + 31] = 0x06;
+
+        let overlay = Overlay::from_color_prom("Part II", &prom).unwrap();
+        assert_eq!(overlay.palette()[overlay.index_at(0, HEIGHT - 1) as usize], Rgb::RED);
+        assert_eq!(overlay.palette()[overlay.index_at(WIDTH - 1, 0) as usize], Rgb(0, 0xFF, 0xFF));
+        assert_eq!(overlay.index_at(100, 100), 0);
+
+        assert!(matches!(Overlay::from_color_prom("Part II", &prom[1..]), Err(Error::InvalidColorProm { size: 0x3FF })));
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let mut video_ram = [0u8; WIDTH * HEIGHT / 8];
+        video_ram[31] = 0b1000_0000;
+
+        let data = encode_png(&video_ram, &Overlay::default(), 2);
+        let decoder = png::Decoder::new(&data[..]);
+        let mut reader = decoder.read_info().unwrap();
+        let mut image = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut image).unwrap();
+
+        assert_eq!((info.width, info.height), (448, 512));
+        assert_eq!(info.color_type, png::ColorType::Indexed);
+        assert_eq!(image[..3], [1, 1, 0]);
+        assert_eq!(image[448..451], [1, 1, 0]);
+        assert_eq!(image[896], 0);
+    }
+
+    #[test]
+    fn test_gif_recorder() {
+        let mut video_ram = [0u8; WIDTH * HEIGHT / 8];
+        let mut recorder = GifRecorder::new(Vec::new(), &Overlay::default()).unwrap();
+        for frame in 0..12 {
+            // Changes every frame, faster than GIF delays can express
+            video_ram[100 * 32 + 10] = frame;
+            recorder.push_frame(&video_ram).unwrap();
+        }
+        for _ in 0..6 {
+            recorder.push_frame(&video_ram).unwrap();
+        }
+
+        let data = recorder.finish().unwrap();
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(&data[..]).unwrap();
+
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.left, frame.top, frame.width, frame.height, frame.delay));
+        }
+
+        assert_eq!(frames.len(), 8);
+        assert_eq!(frames[0], (0, 0, 224, 256, 3));
+        assert!(frames[1..].iter().all(|&(left, _, width, height, delay)| left == 100 && width == 1 && height <= 8 && delay >= 2));
+        assert_eq!(frames.iter().map(|frame| frame.4 as u64).sum::<u64>(), gif_time(18));
+    }
+}
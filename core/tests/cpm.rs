@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::PathBuf;
+
+use core::{CpmMachine, Error};
+
+/// Runs a diagnostic from `tests/roms`. The images aren't distributed with the
+/// crate, so these tests are ignored by default; run them with `--ignored` once
+/// the `.COM` files are in place.
+fn run_diagnostic(name: &str, max_steps: u64) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms").join(name);
+    let program = fs::read(&path).unwrap_or_else(|e| panic!("can't read {}: {}", path.display(), e));
+
+    let mut machine = CpmMachine::new(&program);
+    if let Err(e) = machine.run(max_steps) {
+        panic!("{}: {}\n{}", name, e, machine.output());
+    }
+
+    machine.output().to_owned()
+}
+
+#[test]
+fn test_bdos_console_output() {
+    let program = [
+        0x11, 0x12, 0x01,       // LXI   D,0112h
+        0x0E, 0x09,             // MVI   C,9
+        0xCD, 0x05, 0x00,       // CALL  0005h
+        0x0E, 0x02,             // MVI   C,2
+        0x1E, b'!',             // MVI   E,'!'
+        0xCD, 0x05, 0x00,       // CALL  0005h
+        0xC3, 0x00, 0x00,       // JMP   0000h
+        b'H', b'E', b'L', b'L', b'O', b'$',
+    ];
+
+    let mut machine = CpmMachine::new(&program);
+    machine.run(100).unwrap();
+
+    assert_eq!(machine.output(), "HELLO!");
+}
+
+#[test]
+fn test_step_limit() {
+    let mut machine = CpmMachine::new(&[0xC3, 0x00, 0x01]);  // JMP 0100h
+    assert!(matches!(machine.run(1000), Err(Error::StepLimitExceeded { steps: 1000 })));
+}
+
+#[test]
+#[ignore = "needs TST8080.COM in core/tests/roms"]
+fn test_tst8080() {
+    let output = run_diagnostic("TST8080.COM", 100_000);
+    assert!(output.contains("CPU IS OPERATIONAL"), "{}", output);
+}
+
+#[test]
+#[ignore = "needs 8080PRE.COM in core/tests/roms"]
+fn test_8080pre() {
+    let output = run_diagnostic("8080PRE.COM", 1_000_000);
+    assert!(output.contains("8080 Preliminary tests complete"), "{}", output);
+}
+
+#[test]
+#[ignore = "needs CPUTEST.COM in core/tests/roms, and takes a long time outside of release builds"]
+fn test_cputest() {
+    let output = run_diagnostic("CPUTEST.COM", 1_000_000_000);
+    assert!(output.contains("CPU TESTS OK"), "{}", output);
+}
+
+#[test]
+#[ignore = "needs 8080EXM.COM in core/tests/roms, and takes a long time outside of release builds"]
+fn test_8080exm() {
+    let output = run_diagnostic("8080EXM.COM", 10_000_000_000);
+
+    // Each of the exerciser's tests prints one line ending in its CRC
+    let results: Vec<&str> = output.lines().filter(|line| line.contains("crc")).collect();
+    assert_eq!(results.len(), 25, "{}", output);
+    assert!(results.iter().all(|line| line.contains("PASSED!")), "{}", output);
+    assert!(output.contains("Tests complete"), "{}", output);
+}
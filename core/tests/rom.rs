@@ -0,0 +1,137 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use core::machine::{self, Machine};
+use core::rom::{self, INVADERS_CHIPS};
+use core::video::Overlay;
+use core::{DipSwitches, Emulator, Error};
+
+fn bundled_rom() -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("../frontend/assets/invaders")).unwrap()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("invaders-rom-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_chips(dir: &Path, rom: &[u8]) {
+    for chip in &INVADERS_CHIPS {
+        fs::write(dir.join(chip.name), &rom[chip.offset..chip.offset + chip.size]).unwrap();
+    }
+}
+
+#[test]
+fn test_load_split_set() {
+    let rom = bundled_rom();
+    let dir = temp_dir("split");
+    write_chips(&dir, &rom);
+    assert_eq!(rom::load(&dir).unwrap(), rom);
+
+    // Renamed chips are found by checksum
+    fs::rename(dir.join("invaders.g"), dir.join("ic34.bin")).unwrap();
+    assert_eq!(rom::load(&dir).unwrap(), rom);
+
+    fs::remove_file(dir.join("invaders.f")).unwrap();
+    assert!(matches!(rom::load(&dir), Err(Error::MissingRomChip { name: "invaders.f" })));
+
+    let mut bad = rom[0x1000..0x1800].to_vec();
+    bad[0x10] ^= 0xFF;
+    fs::write(dir.join("invaders.f"), bad).unwrap();
+    assert!(matches!(rom::load(&dir), Err(Error::BadRomChip { name: "invaders.f", expected: 0x0CCEAD96, .. })));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_load_zip() {
+    let rom = bundled_rom();
+    let dir = temp_dir("zip");
+    let path = dir.join("invaders.zip");
+
+    let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+    for chip in INVADERS_CHIPS.iter().rev() {
+        zip.start_file(chip.name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(&rom[chip.offset..chip.offset + chip.size]).unwrap();
+    }
+    zip.finish().unwrap();
+    assert_eq!(rom::load(&path).unwrap(), rom);
+
+    fs::write(&path, b"not a zip").unwrap();
+    assert!(matches!(rom::load(&path), Err(Error::InvalidRomArchive)));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_load_image() {
+    let rom = bundled_rom();
+    assert_eq!(rom::load_image(&rom).unwrap(), rom);
+    assert!(matches!(rom::load_image(&rom[..0x1800]), Err(Error::InvalidRomSize { size: 0x1800 })));
+    assert!(matches!(rom::load(Path::new("does/not/exist")), Err(Error::Io { .. })));
+}
+
+#[test]
+fn test_identify() {
+    let mut rom = bundled_rom();
+    let set = rom::identify(&rom).unwrap();
+    assert_eq!(set.name, "invaders");
+    assert_eq!(set.to_string(), "Space Invaders (Midway, 1978)");
+
+    assert_eq!(set.machine.name, "midway");
+    assert_eq!(set.overlay, "Cellophane");
+
+    rom[0x1234] ^= 0xFF;
+    assert!(rom::identify(&rom).is_none());
+    assert!(rom::identify(&rom[..0x1800]).is_none());
+}
+
+#[test]
+fn test_rom_set_layouts() {
+    for set in rom::ROM_SETS {
+        // The chips tile the machine's ROM image in order, without gaps
+        let mut offset = 0;
+        for chip in set.chips {
+            assert_eq!(chip.offset, offset, "{} {}", set.name, chip.name);
+            assert_eq!(chip.sha1.len(), 40, "{} {}", set.name, chip.name);
+            offset += chip.size;
+        }
+        assert_eq!(offset, set.machine.rom_size(), "{}", set.name);
+        assert_eq!(Machine::from_name(set.machine.name), Some(set.machine), "{}", set.name);
+
+        // Hashes are compared as lowercase hex strings
+        for chip in set.chips {
+            assert!(chip.sha1.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)), "{} {}", set.name, chip.name);
+        }
+        assert!(Overlay::builtin().iter().any(|overlay| overlay.name() == set.overlay), "{}", set.name);
+        assert_eq!(rom::ROM_SETS.iter().filter(|other| other.name == set.name).count(), 1, "{}", set.name);
+    }
+}
+
+#[test]
+fn test_emulator_rom_size() {
+    assert!(matches!(Emulator::new(&[]), Err(Error::InvalidRomSize { size: 0 })));
+    assert!(matches!(Emulator::new(&bundled_rom()[..0x1800]), Err(Error::InvalidRomSize { size: 0x1800 })));
+    assert!(Emulator::new(&bundled_rom()).is_ok());
+}
+
+#[test]
+fn test_detect() {
+    let rom = bundled_rom();
+    assert_eq!(Machine::detect(&rom).unwrap().name, "midway");
+
+    // Unknown sets are only detected when their size is unique to one machine
+    let mut unknown = rom.clone();
+    unknown[0x1234] ^= 0xFF;
+    assert!(matches!(Machine::detect(&unknown), Err(Error::AmbiguousRom { size: 0x2000 })));
+    assert!(matches!(Emulator::new(&unknown), Err(Error::AmbiguousRom { .. })));
+    assert_eq!(Machine::detect(&[0; 0x1000]).unwrap().name, "gunfight");
+    assert_eq!(Machine::detect(&[0; 0x3000]).unwrap().name, "lrescue");
+    assert!(matches!(Machine::detect(&[0; 0x2800]), Err(Error::AmbiguousRom { size: 0x2800 })));
+
+    let emulator = Emulator::with_machine(&machine::BOOT_HILL, &unknown, DipSwitches::default()).unwrap();
+    assert_eq!(emulator.machine().name, "boothill");
+}
@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use core::machine::{Machine, MACHINES};
+use core::rom;
+
+const BUNDLED_ROM: &[u8] = include_bytes!("../assets/invaders");
+
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub rom: Option<PathBuf>,
+    pub machine: Option<&'static Machine>,
+    pub scale: f32,
+    pub fullscreen: bool,
+    pub overlay: Option<String>,
+    pub mute: bool,
+    pub state: Option<PathBuf>,
+    pub play: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub fps: f64,
+    pub debug: bool,
+    pub gdb: Option<u16>,
+}
+
+impl Args {
+    /// Parses the process arguments, exiting with a usage message if they are invalid.
+    pub fn parse() -> Self {
+        Self::from_matches(&command().get_matches())
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Self {
+        Self {
+            rom: matches.get_one("rom").cloned(),
+            machine: matches.get_one("machine").copied(),
+            scale: *matches.get_one("scale").unwrap(),
+            fullscreen: matches.get_flag("fullscreen"),
+            overlay: matches.get_one("overlay").cloned(),
+            mute: matches.get_flag("mute"),
+            state: matches.get_one("state").cloned(),
+            play: matches.get_one("play").cloned(),
+            record: matches.get_one("record").cloned(),
+            fps: *matches.get_one("fps").unwrap(),
+            debug: matches.get_flag("debug"),
+            gdb: matches.get_one("gdb").copied(),
+        }
+    }
+}
+
+fn command() -> Command {
+    let flag = |name: &'static str, help: &'static str| Arg::new(name).long(name).action(ArgAction::SetTrue).help(help);
+    let file = |name: &'static str, help: &'static str| {
+        Arg::new(name).long(name).value_name("FILE").value_parser(value_parser!(PathBuf)).help(help)
+    };
+
+    Command::new("space-invaders")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Space Invaders arcade emulator")
+        .arg(Arg::new("rom").value_name("ROM")
+            .value_parser(value_parser!(PathBuf))
+            .help("ROM image, MAME chip directory or zip, sized for the selected machine (4K to 12K) [default: bundled ROM]"))
+        .arg(Arg::new("machine").long("machine").value_name("NAME").value_parser(parse_machine)
+            .help("Hardware to run the ROM on, such as midway, part2 or gunfight [default: detected from the ROM]"))
+        .arg(Arg::new("scale").long("scale").value_name("N").default_value("2").value_parser(parse_scale)
+            .help("Window size as a multiple of the native resolution"))
+        .arg(flag("fullscreen", "Start in fullscreen mode"))
+        .arg(Arg::new("overlay").long("overlay").value_name("NAME|FILE")
+            .help("Overlay profile name, or a .overlay or .ppm file"))
+        .arg(flag("mute", "Disable sound output"))
+        .arg(file("state", "Save state to start from"))
+        .arg(file("play", "Play back a movie").conflicts_with("record"))
+        .arg(file("record", "Record a movie, written on exit"))
+        .arg(Arg::new("fps").long("fps").value_name("N").default_value("60").value_parser(parse_fps)
+            .help("Frames shown per second; the game runs faster or slower accordingly"))
+        .arg(flag("debug", "Start in the interactive debugger"))
+        .arg(Arg::new("gdb").long("gdb").value_name("PORT").value_parser(value_parser!(u16))
+            .help("Wait for a GDB remote protocol client on this port"))
+}
+
+/// Loads the ROM from an image, split set or zip, falling back to the bundled one.
+pub fn load_rom(path: Option<&Path>) -> Result<Vec<u8>, String> {
+    match path {
+        Some(path) => rom::load(path).map_err(|e| format!("could not load ROM from {}: {}", path.display(), e)),
+        None => Ok(BUNDLED_ROM.to_vec()),
+    }
+}
+
+fn parse_machine(value: &str) -> Result<&'static Machine, String> {
+    Machine::from_name(value).ok_or_else(|| {
+        let names: Vec<&str> = MACHINES.iter().map(|machine| machine.name).collect();
+        format!("expected one of: {}", names.join(", "))
+    })
+}
+
+fn parse_scale(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(scale) if scale > 0.0 && scale <= 16.0 => Ok(scale),
+        _ => Err("expected a number between 0 and 16".to_owned()),
+    }
+}
+
+fn parse_fps(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fps) if (1.0..=1000.0).contains(&fps) => Ok(fps),
+        _ => Err("expected a number between 1 and 1000".to_owned()),
+    }
+}
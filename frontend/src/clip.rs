@@ -0,0 +1,35 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use core::video::{GifRecorder, Overlay};
+
+pub const CLIP_DIR: &str = "clips";
+
+/// An animated GIF being recorded. The overlay is fixed when the clip starts.
+pub struct Clip {
+    recorder: GifRecorder<BufWriter<File>>,
+    path: PathBuf,
+}
+
+impl Clip {
+    /// Starts a clip in the first unused `clips/clipNNN.gif` file.
+    pub fn start(overlay: &Overlay) -> Result<Self, String> {
+        fs::create_dir_all(CLIP_DIR).map_err(|e| e.to_string())?;
+
+        let path = crate::unused_path(CLIP_DIR, "clip", &["gif"]).with_extension("gif");
+        let file = File::create(&path).map_err(|e| format!("could not create {}: {}", path.display(), e))?;
+        let recorder = GifRecorder::new(BufWriter::new(file), overlay).map_err(|e| e.to_string())?;
+
+        Ok(Self { recorder, path })
+    }
+
+    pub fn push_frame(&mut self, video_ram: &[u8]) -> Result<(), String> {
+        self.recorder.push_frame(video_ram).map_err(|e| e.to_string())
+    }
+
+    pub fn finish(self) -> Result<PathBuf, String> {
+        self.recorder.finish().and_then(|mut writer| writer.flush()).map_err(|e| e.to_string())?;
+        Ok(self.path)
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+
+use core::DipSwitches;
+
+pub const CONFIG_PATH: &str = "invaders.cfg";
+
+/// Settings kept between runs, stored as `<key> <value>` lines with `#` comments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Config {
+    pub dip_switches: DipSwitches,
+}
+
+impl Config {
+    /// Loads `CONFIG_PATH`, or the defaults if it doesn't exist yet.
+    pub fn load() -> Result<Self, String> {
+        match fs::read_to_string(CONFIG_PATH) {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("{}: {}", CONFIG_PATH, e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("could not read {}: {}", CONFIG_PATH, e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        fs::write(CONFIG_PATH, self.to_string()).map_err(|e| format!("could not write {}: {}", CONFIG_PATH, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        let dips = &mut config.dip_switches;
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "lives" => {
+                    dips.lives = value
+                        .parse()
+                        .ok()
+                        .filter(|lives| (DipSwitches::MIN_LIVES..=DipSwitches::MAX_LIVES).contains(lives))
+                        .ok_or_else(|| format!("line {}: lives must be between 3 and 6", i + 1))?;
+                }
+                "bonus_life" => {
+                    dips.bonus_life_at_1000 = match value {
+                        "1000" => true,
+                        "1500" => false,
+                        _ => return Err(format!("line {}: bonus_life must be 1000 or 1500", i + 1)),
+                    };
+                }
+                "coin_info" => {
+                    dips.coin_info = match value {
+                        "on" => true,
+                        "off" => false,
+                        _ => return Err(format!("line {}: coin_info must be on or off", i + 1)),
+                    };
+                }
+                _ => return Err(format!("line {}: unknown setting `{}`", i + 1, key)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dips = &self.dip_switches;
+        writeln!(f, "# DIP switches, read by the game at power-on")?;
+        writeln!(f, "lives {}", dips.lives)?;
+        writeln!(f, "bonus_life {}", dips.bonus_life())?;
+        writeln!(f, "coin_info {}", if dips.coin_info { "on" } else { "off" })
+    }
+}
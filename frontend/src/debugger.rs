@@ -0,0 +1,220 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+use core::{disasm, Emulator, ExecutionStatus, Register};
+use core::{CARRY_FLAG, PARITY_FLAG, AUX_CARRY_FLAG, ZERO_FLAG, SIGN_FLAG};
+
+const HELP: &str = "\
+commands:
+  s, step [n]          execute n instructions (default 1)
+  c, continue          resume execution
+  r, run <adr>         resume execution until PC reaches adr
+  b, break <adr>       set a breakpoint
+  d, delete <adr>      remove a breakpoint
+  bl, breakpoints      list breakpoints
+  regs                 dump registers
+  set <reg> <val>      set a register (a, b, c, d, e, h, l, f, sp, pc)
+  x <adr> [len]        hex dump memory
+  l, list [adr] [n]    disassemble n instructions (default: from PC)
+  q, quit              exit the emulator
+numbers are hexadecimal, optionally prefixed with 0x or $";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Quit,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    run_to: Option<u16>,
+    break_requested: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn request_break(&mut self) {
+        self.break_requested = true;
+    }
+
+    pub fn should_break(&mut self, emulator: &Emulator) -> bool {
+        let pc = emulator.cpu().pc();
+
+        if self.run_to == Some(pc) {
+            self.run_to = None;
+            return true;
+        }
+
+        self.breakpoints.contains(&pc) || std::mem::take(&mut self.break_requested)
+    }
+
+    pub fn repl(&mut self, emulator: &mut Emulator) -> Result<Action, String> {
+        self.break_requested = false;
+        print_location(emulator);
+
+        let stdin = io::stdin();
+        let mut line = String::new();
+
+        loop {
+            print!("(debug) ");
+            io::stdout().flush().map_err(|e| e.to_string())?;
+
+            line.clear();
+            if stdin.lock().read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+                return Ok(Action::Quit);
+            }
+
+            let args: Vec<&str> = line.split_whitespace().collect();
+            let Some(&command) = args.first() else { continue };
+
+            match self.execute(command, &args[1..], emulator) {
+                Ok(Some(action)) => return Ok(action),
+                Ok(None) => {}
+                Err(e) => println!("error: {}", e),
+            }
+        }
+    }
+
+    fn execute(&mut self, command: &str, args: &[&str], emulator: &mut Emulator) -> Result<Option<Action>, String> {
+        match command {
+            "s" | "step" => {
+                let count = args.first().map(|n| n.parse::<u32>().map_err(|e| e.to_string())).transpose()?.unwrap_or(1);
+                for _ in 0..count {
+                    if let ExecutionStatus::Halt = emulator.step().map_err(|e| e.to_string())? {
+                        println!("CPU halted");
+                        break;
+                    }
+                }
+                print_location(emulator);
+            }
+            "c" | "continue" => return Ok(Some(Action::Continue)),
+            "r" | "run" => {
+                self.run_to = Some(parse_u16(arg(args, 0)?)?);
+                return Ok(Some(Action::Continue));
+            }
+            "b" | "break" => {
+                let adr = parse_u16(arg(args, 0)?)?;
+                self.breakpoints.insert(adr);
+                println!("breakpoint set at {:04X}", adr);
+            }
+            "d" | "delete" => {
+                let adr = parse_u16(arg(args, 0)?)?;
+                if !self.breakpoints.remove(&adr) {
+                    return Err(format!("no breakpoint at {:04X}", adr));
+                }
+            }
+            "bl" | "breakpoints" => {
+                for adr in &self.breakpoints {
+                    println!("{:04X}", adr);
+                }
+            }
+            "regs" => print_registers(emulator),
+            "set" => {
+                let val = parse_u16(arg(args, 1)?)?;
+                set_register(emulator, arg(args, 0)?, val)?;
+                print_registers(emulator);
+            }
+            "x" => {
+                let adr = parse_u16(arg(args, 0)?)?;
+                let len = args.get(1).map(|n| parse_u16(n)).transpose()?.unwrap_or(0x40);
+                hex_dump(emulator, adr, len);
+            }
+            "l" | "list" => {
+                let adr = args.first().map(|n| parse_u16(n)).transpose()?.unwrap_or(emulator.cpu().pc());
+                let count = args.get(1).map(|n| n.parse::<usize>().map_err(|e| e.to_string())).transpose()?.unwrap_or(10);
+                for instruction in disasm::disassemble_range(emulator.memory(), adr, count) {
+                    print_instruction(&instruction, emulator.cpu().pc());
+                }
+            }
+            "q" | "quit" => return Ok(Some(Action::Quit)),
+            "h" | "help" => println!("{}", HELP),
+            _ => return Err(format!("unknown command: {} (try 'help')", command)),
+        }
+
+        Ok(None)
+    }
+}
+
+fn arg<'a>(args: &[&'a str], index: usize) -> Result<&'a str, String> {
+    args.get(index).copied().ok_or_else(|| "missing argument".to_owned())
+}
+
+fn parse_u16(s: &str) -> Result<u16, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches('$');
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid number: {}", s))
+}
+
+fn set_register(emulator: &mut Emulator, name: &str, val: u16) -> Result<(), String> {
+    let cpu = emulator.cpu_mut();
+
+    let register = match name.to_lowercase().as_str() {
+        "pc" => {
+            cpu.set_pc(val);
+            return Ok(());
+        }
+        "sp" => {
+            cpu.set_sp(val);
+            return Ok(());
+        }
+        "a" => Register::A,
+        "b" => Register::B,
+        "c" => Register::C,
+        "d" => Register::D,
+        "e" => Register::E,
+        "h" => Register::H,
+        "l" => Register::L,
+        "f" | "flags" => Register::Flags,
+        _ => return Err(format!("unknown register: {}", name)),
+    };
+
+    let val = u8::try_from(val).map_err(|_| format!("value out of range for {}: {:X}", name, val))?;
+    cpu.set_register(register, val);
+    Ok(())
+}
+
+fn print_location(emulator: &Emulator) {
+    print_registers(emulator);
+    let instruction = disasm::disassemble(emulator.memory(), emulator.cpu().pc());
+    print_instruction(&instruction, emulator.cpu().pc());
+}
+
+fn print_instruction(instruction: &disasm::Instruction, pc: u16) {
+    let bytes: Vec<String> = instruction.bytes().iter().map(|b| format!("{:02X}", b)).collect();
+    let marker = if instruction.address == pc { '>' } else { ' ' };
+    println!("{} {:04X}  {:<9} {}", marker, instruction.address, bytes.join(" "), instruction);
+}
+
+fn print_registers(emulator: &Emulator) {
+    let cpu = emulator.cpu();
+    let flags = cpu.register(Register::Flags);
+
+    let flag_chars: String = [(SIGN_FLAG, 'S'), (ZERO_FLAG, 'Z'), (AUX_CARRY_FLAG, 'A'), (PARITY_FLAG, 'P'), (CARRY_FLAG, 'C')]
+        .iter()
+        .map(|&(flag, c)| if flags & flag != 0 { c } else { '-' })
+        .collect();
+
+    println!(
+        "A={:02X} B={:02X} C={:02X} D={:02X} E={:02X} H={:02X} L={:02X} SP={:04X} PC={:04X} F={} IE={}",
+        cpu.register(Register::A), cpu.register(Register::B), cpu.register(Register::C),
+        cpu.register(Register::D), cpu.register(Register::E), cpu.register(Register::H),
+        cpu.register(Register::L), cpu.sp(), cpu.pc(), flag_chars, cpu.interrupts_enabled() as u8,
+    );
+}
+
+fn hex_dump(emulator: &Emulator, adr: u16, len: u16) {
+    let memory = emulator.memory();
+
+    for line_start in (0..len).step_by(16) {
+        let line_adr = adr.wrapping_add(line_start);
+        let line_len = (len - line_start).min(16);
+        let bytes: Vec<u8> = (0..line_len).map(|i| memory[line_adr.wrapping_add(i)]).collect();
+
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let ascii: String = bytes.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+        println!("{:04X}: {:<47}  {}", line_adr, hex.join(" "), ascii);
+    }
+}
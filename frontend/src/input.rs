@@ -1,20 +1,8 @@
 use sdl2::keyboard::Keycode;
 
-use core::{Emulator, Button};
+use core::Button;
 
-pub fn handle_keydown(keycode: Keycode, emulator: &mut Emulator) {
-    if let Some(button) = map_keycode(keycode) {
-        emulator.button_press(button);
-    }
-}
-
-pub fn handle_keyup(keycode: Keycode, emulator: &mut Emulator) {
-    if let Some(button) = map_keycode(keycode) {
-        emulator.button_release(button);
-    }
-}
-
-fn map_keycode(keycode: Keycode) -> Option<Button> {
+pub fn map_keycode(keycode: Keycode) -> Option<Button> {
     Some(match keycode {
         Keycode::C => Button::Coin,
         Keycode::Return => Button::P1Start,
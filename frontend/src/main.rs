@@ -1,57 +1,144 @@
 #![windows_subsystem = "windows"]
 
+use std::fs;
 use std::time::{Duration, Instant};
 use colored::Colorize;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
-use sdl2::rect::Rect;
 
-use core::{Emulator, ExecutionStatus, EmulatorEvent, Sound};
-use frontend::input;
+use core::{DipSwitches, Emulator, Error, RewindBuffer};
+use core::machine::{Coloring, MACHINES};
+use core::rom::{self, RomSet};
+use core::gdb::{self, Action as GdbAction, GdbStub};
+use core::video::{self, Overlay, PixelFormat};
+use frontend::{cli, input, movie, overlay, savestate, screenshot};
+use frontend::cli::Args;
+use frontend::config::Config;
+use frontend::clip::Clip;
+use frontend::recording::Recorder;
+use frontend::movie::MovieState;
 use frontend::{WIDTH, HEIGHT};
 use frontend::audio::AudioManager;
+use frontend::debugger::{Action, Debugger};
 
-const SCALE_X: f32 = 2.0;
-const SCALE_Y: f32 = 2.5;
-const FPS: f64 = 60.0;
-const CYCLES_PER_FRAME: u32 = (2_000_000.0 / FPS) as u32;
+/// The monitor's pixels are taller than they are wide.
+const PIXEL_ASPECT: f32 = 1.25;
+const REWIND_INTERVAL: u64 = 2;
+const REWIND_CAPACITY: usize = 16 * 1024 * 1024;
 
 fn main() {
-    let program = include_bytes!("../assets/invaders");
+    let args = Args::parse();
 
-    run(program).unwrap_or_else(|e| {
+    run(&args).unwrap_or_else(|e| {
         eprintln!("{} {}", "Error:".red().bold(), e.to_string().red())
     });
 }
 
-fn run(program: &[u8]) -> Result<(), String> {
+fn run(args: &Args) -> Result<(), String> {
+    let program = cli::load_rom(args.rom.as_deref())?;
+    let config = Config::load()?;
+    let mut emulator = match args.machine {
+        Some(machine) => Emulator::with_machine(machine, &program, config.dip_switches),
+        None => Emulator::with_dip_switches(&program, config.dip_switches),
+    }
+    .map_err(|e| match e {
+        Error::AmbiguousRom { size } => format!("{}, choose one with --machine <{}>", e, machine_names(size)),
+        e => e.to_string(),
+    })?;
+
+    let machine = emulator.machine();
+    let identified = rom::identify(&program);
+    match identified {
+        Some(set) if set.machine == machine => println!("Loaded {}", set),
+        Some(set) => println!("Loaded {}, running on the {} hardware", set, machine.title),
+        None => println!("Unrecognized ROM (CRC32 {:08x}), running on the {} hardware", emulator.rom_hash(), machine.title),
+    }
+    // A set's title and overlay only apply on its own hardware
+    let set = identified.filter(|set| set.machine == machine);
+
+    if let Some(path) = &args.state {
+        let data = fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        emulator.load_state(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+
+    let movie = match (&args.play, &args.record) {
+        (Some(path), _) => MovieState::play(&mut emulator, path)?,
+        (None, Some(path)) => MovieState::record(&mut emulator, path.clone(), args.state.is_none()),
+        (None, None) => MovieState::Idle,
+    };
+
+    let mut overlays = overlay::load_overlays()?;
+    let overlay_index = match &args.overlay {
+        Some(name) => overlay::select(&mut overlays, name)?,
+        None => match (set, machine.coloring) {
+            (Some(set), _) => overlay::select(&mut overlays, set.overlay)?,
+            (None, Coloring::Overlay(name)) => overlay::select(&mut overlays, name)?,
+            (None, Coloring::ColorProm) => {
+                println!("{} colors come from its color PROM, pass it with --overlay <file.prom>", machine.title);
+                0
+            }
+        },
+    };
+
+    run_window(args, set, config, emulator, movie, overlays, overlay_index)
+}
+
+fn run_window(args: &Args, set: Option<&RomSet>, mut config: Config, mut emulator: Emulator, mut movie: MovieState, overlays: Vec<Overlay>, mut overlay_index: usize) -> Result<(), String> {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("Space Invaders", (WIDTH as f32 * SCALE_X) as u32, (HEIGHT as f32 * SCALE_Y) as u32)
-        .position_centered()
-        .build().expect("could not build window");
+    let (width, height) = (WIDTH as f32 * args.scale, HEIGHT as f32 * args.scale * PIXEL_ASPECT);
+    let mut window = video_subsystem.window(set.map_or(emulator.machine().title, |set| set.title), width as u32, height as u32);
+    window.position_centered();
+    if args.fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().map_err(|e| format!("could not build window: {}", e))?;
 
-    let audio_subsystem = sdl_context.audio()?;
-    let mut audio = AudioManager::new(audio_subsystem)?;
+    let mut audio = match args.mute {
+        true => AudioManager::muted()?,
+        false => AudioManager::new(sdl_context.audio()?)?,
+    };
 
     let mut event_pump = sdl_context.event_pump()?;
     let mut canvas = window.into_canvas().present_vsync().build().expect("could not build renderer");
 
-    canvas.set_scale(SCALE_X, SCALE_Y)?;
+    canvas.set_logical_size(WIDTH as u32 * 4, (HEIGHT as f32 * 4.0 * PIXEL_ASPECT) as u32).map_err(|e| e.to_string())?;
     canvas.present();
 
     let creator = canvas.texture_creator();
     let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, HEIGHT, WIDTH)
+        .create_texture_target(PixelFormatEnum::RGB24, WIDTH as u32, HEIGHT as u32)
         .expect("could not create texture");
 
-    let mut pixel_data = [0; (WIDTH * HEIGHT * 3) as usize];
+    let mut pixel_data = vec![0; PixelFormat::Rgb24.buffer_size()];
 
-    let mut emulator = Emulator::new(program);
-    let mut save_state: Option<Emulator> = None;
+    let mut slot: u8 = 0;
     let mut paused = false;
+    let mut rewind = RewindBuffer::new(REWIND_CAPACITY);
+    let mut rewinding = false;
+    let mut emulated_frame: u64 = 0;
+    let mut recorder: Option<Recorder> = None;
+    let mut clip: Option<Clip> = None;
+
+    let mut gdb = match args.gdb {
+        Some(port) => {
+            println!("Waiting for GDB connection on 127.0.0.1:{}", port);
+            let mut stub = GdbStub::listen(port).map_err(|e| e.to_string())?;
+
+            match stub.wait(&mut emulator).map_err(|e| e.to_string())? {
+                GdbAction::Continue => Some(stub),
+                GdbAction::Detach => None,
+                GdbAction::Kill => return Ok(()),
+            }
+        }
+        None => None,
+    };
+
+    let mut debugger = args.debug.then(Debugger::new);
+    if let Some(debugger) = &mut debugger {
+        debugger.request_break();
+    }
 
     let now = Instant::now();
     let mut frame: u64 = 0;
@@ -63,67 +150,254 @@ fn run(program: &[u8]) -> Result<(), String> {
                 Event::KeyDown { keycode: Some(keycode), keymod, .. } if frontend::has_ctrl(keymod) => {
                     match keycode {
                         Keycode::Q => break 'main,
-                        Keycode::S => save_state = Some(emulator.clone()),
-                        Keycode::D => {
-                            if let Some(state) = &save_state {
-                                emulator = state.clone();
+                        Keycode::S => match savestate::save(&emulator, slot) {
+                            Ok(()) => println!("Saved state to slot {}", slot),
+                            Err(e) => eprintln!("{} {}", "Could not save state:".red().bold(), e.red()),
+                        },
+                        Keycode::D => match savestate::load(&mut emulator, slot) {
+                            Ok(()) => {
+                                stop_movie(&mut movie);
+                                audio.stop_all();
+                                println!("Loaded state from slot {}", slot);
                             }
-                        }
+                            Err(e) => eprintln!("{} {}", "Could not load state:".red().bold(), e.red()),
+                        },
                         Keycode::R => {
-                            emulator.cpu_mut().reset();
+                            stop_movie(&mut movie);
+                            emulator.reset();
                             audio.stop_all();
                         }
+                        Keycode::M | Keycode::N if movie.is_idle() => {
+                            movie = MovieState::record(&mut emulator, movie::default_path(), keycode == Keycode::N);
+                            emulated_frame = 0;
+                            audio.stop_all();
+                            println!("Recording movie");
+                        }
+                        Keycode::M | Keycode::N => stop_movie(&mut movie),
+                        Keycode::P => {
+                            stop_movie(&mut movie);
+                            match MovieState::play(&mut emulator, &movie::default_path()) {
+                                Ok(state) => {
+                                    movie = state;
+                                    emulated_frame = 0;
+                                    audio.stop_all();
+                                    println!("Playing movie");
+                                }
+                                Err(e) => eprintln!("{} {}", "Could not play movie:".red().bold(), e.red()),
+                            }
+                        }
+                        Keycode::Num0 | Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4
+                        | Keycode::Num5 | Keycode::Num6 | Keycode::Num7 | Keycode::Num8 | Keycode::Num9 => {
+                            slot = (keycode.into_i32() - Keycode::Num0.into_i32()) as u8 % savestate::SLOTS;
+                            println!("Selected save slot {}", slot);
+                        }
+                        Keycode::B => {
+                            if let Some(debugger) = &mut debugger {
+                                debugger.request_break();
+                            }
+                        }
+                        Keycode::V => match recorder.take() {
+                            Some(active) => stop_recording(active),
+                            None => match Recorder::start() {
+                                Ok(started) => {
+                                    recorder = Some(started);
+                                    println!("Recording video");
+                                }
+                                Err(e) => eprintln!("{} {}", "Could not start recording:".red().bold(), e.red()),
+                            },
+                        },
+                        Keycode::G => match clip.take() {
+                            Some(active) => stop_clip(active),
+                            None => match Clip::start(&overlays[overlay_index]) {
+                                Ok(started) => {
+                                    clip = Some(started);
+                                    println!("Recording GIF clip");
+                                }
+                                Err(e) => eprintln!("{} {}", "Could not start clip:".red().bold(), e.red()),
+                            },
+                        },
+                        Keycode::O => {
+                            overlay_index = (overlay_index + 1) % overlays.len();
+                            println!("Selected overlay: {}", overlays[overlay_index].name());
+                        }
                         _ => {}
                     };
                 }
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => paused = !paused,
-                Event::KeyDown { keycode: Some(k), .. } => input::handle_keydown(k, &mut emulator),
-                Event::KeyUp { keycode: Some(k), .. } => input::handle_keyup(k, &mut emulator),
+                Event::KeyDown { keycode: Some(Keycode::F12), keymod, repeat: false, .. } => {
+                    let overlay = (!frontend::has_alt(keymod)).then(|| &overlays[overlay_index]);
+                    let scale = if frontend::has_shift(keymod) { screenshot::SCALE } else { 1 };
+                    match screenshot::save(&emulator, overlay, scale) {
+                        Ok(path) => println!("Saved screenshot to {}", path.display()),
+                        Err(e) => eprintln!("{} {}", "Could not save screenshot:".red().bold(), e.red()),
+                    }
+                }
+                Event::KeyDown { keycode: Some(keycode @ (Keycode::F1 | Keycode::F2 | Keycode::F3)), repeat: false, .. } => {
+                    let dips = &mut config.dip_switches;
+                    match keycode {
+                        Keycode::F1 if dips.lives >= DipSwitches::MAX_LIVES => dips.lives = DipSwitches::MIN_LIVES,
+                        Keycode::F1 => dips.lives += 1,
+                        Keycode::F2 => dips.bonus_life_at_1000 = !dips.bonus_life_at_1000,
+                        _ => dips.coin_info = !dips.coin_info,
+                    }
+
+                    stop_movie(&mut movie);
+                    emulator.set_dip_switches(config.dip_switches);
+                    print_dip_switches(config.dip_switches);
+                    if let Err(e) = config.save() {
+                        eprintln!("{} {}", "Could not save settings:".red().bold(), e.red());
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => {
+                    stop_movie(&mut movie);
+                    rewinding = true;
+                }
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => rewinding = false,
+                Event::KeyDown { keycode: Some(k), repeat: false, .. } => {
+                    if let Some(button) = input::map_keycode(k) {
+                        movie.button(&mut emulator, emulated_frame, button, true);
+                    }
+                }
+                Event::KeyUp { keycode: Some(k), .. } => {
+                    if let Some(button) = input::map_keycode(k) {
+                        movie.button(&mut emulator, emulated_frame, button, false);
+                    }
+                }
                 _ => {}
             }
         }
 
-        if !paused {
-            let mut cycles = 0;
-            let mut isr_done = false;
+        if !paused && rewinding {
+            if rewind.rewind(&mut emulator).map_err(|e| e.to_string())? {
+                audio.stop_all();
+            }
+        } else if !paused {
+            movie.start_frame(&mut emulator, emulated_frame);
 
-            while cycles < CYCLES_PER_FRAME {
-                let status = emulator.step().map_err(|e| e.to_string())?;
-                match status {
-                    ExecutionStatus::Continue(c) => cycles += c * 4,
-                    ExecutionStatus::Halt => break,
-                }
+            let interrupted = match &mut gdb {
+                Some(stub) => stub.interrupt_requested().map_err(|e| e.to_string())?,
+                None => false,
+            };
+            if interrupted && gdb_stop(&mut gdb, &mut emulator, gdb::SIGINT)? {
+                break 'main;
+            }
 
-                // Handle sounds
-                if let Some(event) = emulator.event() {
-                    match event {
-                        EmulatorEvent::PlaySound(sound) => audio.play(sound),
-                        EmulatorEvent::StopSound(Sound::UFO) => audio.stop(Sound::UFO),
-                        _ => {}
+            let current_frame = emulator.frame();
+            let frame_start = emulator.cycles();
+            let mut frame_events = Vec::new();
+            while emulator.frame() == current_frame {
+                if let Some(debugger) = &mut debugger {
+                    if debugger.should_break(&emulator) && debugger.repl(&mut emulator)? == Action::Quit {
+                        break 'main;
                     }
                 }
 
-                // Mid-line interrupt
-                if !isr_done && cycles >= CYCLES_PER_FRAME / 2 {
-                    emulator.cpu_mut().interrupt(1);
-                    isr_done = true;
+                let hit_breakpoint = gdb.as_ref().is_some_and(|stub| stub.should_break(&emulator));
+                if hit_breakpoint && gdb_stop(&mut gdb, &mut emulator, gdb::SIGTRAP)? {
+                    break 'main;
+                }
+
+                emulator.step().map_err(|e| e.to_string())?;
+                frame_events.extend(emulator.events());
+            }
+
+            let samples = audio.end_frame(&frame_events, frame_start, emulator.cycles())?;
+            if let Some(active) = &mut recorder {
+                if let Err(e) = active.push_frame(emulator.video_ram(), &overlays[overlay_index], &samples) {
+                    eprintln!("{} {}", "Recording stopped:".red().bold(), e.red());
+                    recorder = None;
+                }
+            }
+            if let Some(active) = &mut clip {
+                if let Err(e) = active.push_frame(emulator.video_ram()) {
+                    eprintln!("{} {}", "Clip stopped:".red().bold(), e.red());
+                    clip = None;
                 }
             }
 
-            emulator.cpu_mut().interrupt(2); // VBlank interrupt
+            match movie.end_frame(&emulator, emulated_frame) {
+                Ok(true) => println!("Movie playback finished"),
+                Ok(false) => {}
+                Err(e) => eprintln!("{} {}", "Movie playback stopped:".red().bold(), e.red()),
+            }
+            emulated_frame += 1;
+
+            if emulated_frame.is_multiple_of(REWIND_INTERVAL) {
+                rewind.push(&emulator);
+            }
         }
 
-        if frontend::update_pixel_data(&mut pixel_data, emulator.video_ram()) {
-            texture.update(None, &pixel_data, HEIGHT as usize * 3).unwrap();
-            canvas.copy_ex(&texture, None, Rect::from_center(canvas.viewport().center(), HEIGHT, WIDTH), -90.0, None, false, false)?;
+        if video::render(emulator.video_ram(), &overlays[overlay_index], PixelFormat::Rgb24, &mut pixel_data) {
+            texture.update(None, &pixel_data, PixelFormat::Rgb24.pitch()).unwrap();
+            canvas.copy(&texture, None, None)?;
             canvas.present();
         }
 
         frame += 1;
-        let next_frame = ((1_000.0 / FPS) * frame as f64) as u64;
+        let next_frame = ((1_000.0 / args.fps) * frame as f64) as u64;
         let sleep_ms = next_frame.saturating_sub(now.elapsed().as_millis() as u64);
         spin_sleep::sleep(Duration::from_millis(sleep_ms));
     }
 
+    stop_movie(&mut movie);
+    if let Some(active) = recorder {
+        stop_recording(active);
+    }
+    if let Some(active) = clip {
+        stop_clip(active);
+    }
+
     Ok(())
 }
+
+fn print_dip_switches(dips: DipSwitches) {
+    println!(
+        "DIP switches: {} lives, bonus life at {}, coin info {} (reset with Ctrl+R to apply)",
+        dips.lives,
+        dips.bonus_life(),
+        if dips.coin_info { "on" } else { "off" },
+    );
+}
+
+fn stop_recording(recorder: Recorder) {
+    match recorder.finish() {
+        Ok(path) => println!("Saved recording to {}.y4m and .wav", path.display()),
+        Err(e) => eprintln!("{} {}", "Could not save recording:".red().bold(), e.red()),
+    }
+}
+
+fn stop_clip(clip: Clip) {
+    match clip.finish() {
+        Ok(path) => println!("Saved clip to {}", path.display()),
+        Err(e) => eprintln!("{} {}", "Could not save clip:".red().bold(), e.red()),
+    }
+}
+
+fn stop_movie(movie: &mut MovieState) {
+    match movie.stop() {
+        Ok(Some(path)) => println!("Saved movie to {}", path.display()),
+        Ok(None) => {}
+        Err(e) => eprintln!("{} {}", "Could not save movie:".red().bold(), e.red()),
+    }
+}
+
+/// Names of the machines with a ROM of `size` bytes, separated by `|`.
+fn machine_names(size: usize) -> String {
+    let names: Vec<&str> = MACHINES.iter().filter(|machine| machine.rom_size() == size).map(|machine| machine.name).collect();
+    names.join("|")
+}
+
+/// Reports a stop to the GDB client and waits for it to resume. Returns
+/// `true` if the client asked to kill the emulator.
+fn gdb_stop(gdb: &mut Option<GdbStub>, emulator: &mut Emulator, signal: u8) -> Result<bool, String> {
+    let Some(stub) = gdb else { return Ok(false) };
+
+    match stub.stop(emulator, signal).map_err(|e| e.to_string())? {
+        GdbAction::Continue => Ok(false),
+        GdbAction::Detach => {
+            *gdb = None;
+            Ok(false)
+        }
+        GdbAction::Kill => Ok(true),
+    }
+}
@@ -0,0 +1,232 @@
+use core::{EmulatorEvent, Sound, TimedEvent, CLOCK_SPEED};
+
+pub const SAMPLE_RATE: u32 = 44100;
+const VOLUME: f32 = 0.25;
+
+struct Voice {
+    samples: Vec<i16>,
+    position: usize,
+    playing: bool,
+    looping: bool,
+}
+
+/// Mixes the game's sounds in emulated time, so the output for a frame only depends on
+/// the events raised during it and not on how fast the emulator runs.
+pub struct Mixer {
+    voices: Vec<Voice>,
+}
+
+impl Mixer {
+    pub fn new() -> Result<Self, String> {
+        let wavs: [&[u8]; 9] = [
+            include_bytes!("../assets/audio/0.wav"),
+            include_bytes!("../assets/audio/1.wav"),
+            include_bytes!("../assets/audio/2.wav"),
+            include_bytes!("../assets/audio/3.wav"),
+            include_bytes!("../assets/audio/4.wav"),
+            include_bytes!("../assets/audio/5.wav"),
+            include_bytes!("../assets/audio/6.wav"),
+            include_bytes!("../assets/audio/7.wav"),
+            include_bytes!("../assets/audio/8.wav"),
+        ];
+
+        let voices = wavs.iter().enumerate()
+            .map(|(i, wav)| {
+                let samples = decode_wav(wav).map_err(|e| format!("sound {}: {}", i, e))?;
+                Ok(Voice { samples, position: 0, playing: false, looping: i == 0 })
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { voices })
+    }
+
+    pub fn play(&mut self, sound: Sound) {
+        let voice = &mut self.voices[sound_index(sound)];
+        voice.position = 0;
+        voice.playing = true;
+    }
+
+    pub fn stop(&mut self, sound: Sound) {
+        self.voices[sound_index(sound)].playing = false;
+    }
+
+    pub fn stop_all(&mut self) {
+        for voice in &mut self.voices {
+            voice.playing = false;
+        }
+    }
+
+    pub fn handle(&mut self, event: &EmulatorEvent) {
+        match *event {
+            EmulatorEvent::PlaySound(sound) => self.play(sound),
+            // One-shot sounds always play to the end
+            EmulatorEvent::StopSound(sound) if self.voices[sound_index(sound)].looping => self.stop(sound),
+            _ => {}
+        }
+    }
+
+    /// Mixes the samples for the emulated cycles `start..end`, applying each event at its cycle.
+    pub fn mix_frame(&mut self, events: &[TimedEvent], start: u64, end: u64) -> Vec<i16> {
+        let mut out = Vec::new();
+        let mut position = sample_at(start);
+        let end = sample_at(end).max(position);
+
+        for TimedEvent { cycle, event } in events {
+            let sample = sample_at(*cycle).clamp(position, end);
+            self.mix(&mut out, (sample - position) as usize);
+            self.handle(event);
+            position = sample;
+        }
+
+        self.mix(&mut out, (end - position) as usize);
+        out
+    }
+
+    fn mix(&mut self, out: &mut Vec<i16>, count: usize) {
+        for _ in 0..count {
+            let mut sum = 0.0;
+
+            for voice in self.voices.iter_mut().filter(|voice| voice.playing) {
+                sum += voice.samples[voice.position] as f32 * VOLUME;
+                voice.position += 1;
+
+                if voice.position >= voice.samples.len() {
+                    voice.position = 0;
+                    voice.playing = voice.looping;
+                }
+            }
+
+            out.push(sum.clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+        }
+    }
+}
+
+fn sound_index(sound: Sound) -> usize {
+    match sound {
+        Sound::UFO => 0,
+        Sound::Shoot => 1,
+        Sound::PlayerDie => 2,
+        Sound::InvaderDie => 3,
+        Sound::Bomp1 => 4,
+        Sound::Bomp2 => 5,
+        Sound::Bomp3 => 6,
+        Sound::Bomp4 => 7,
+        Sound::UFOExplode => 8,
+    }
+}
+
+fn sample_at(cycle: u64) -> u64 {
+    cycle * SAMPLE_RATE as u64 / CLOCK_SPEED as u64
+}
+
+/// Decodes a mono 8 or 16-bit PCM WAV file, resampled to `SAMPLE_RATE`.
+fn decode_wav(data: &[u8]) -> Result<Vec<i16>, String> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err("not a WAV file".to_owned());
+    }
+
+    let mut format = None;
+    let mut pcm = None;
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let size = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let chunk = data.get(pos + 8..pos + 8 + size).ok_or("truncated WAV chunk")?;
+        match &data[pos..pos + 4] {
+            b"fmt " if size >= 16 => format = Some(chunk),
+            b"data" => pcm = Some(chunk),
+            _ => {}
+        }
+        pos += 8 + size + (size & 1);
+    }
+
+    let (format, pcm) = format.zip(pcm).ok_or("missing fmt or data chunk")?;
+    let field = |offset: usize| u16::from_le_bytes([format[offset], format[offset + 1]]);
+    let rate = u32::from_le_bytes(format[4..8].try_into().unwrap());
+    if field(0) != 1 || field(2) != 1 || rate == 0 {
+        return Err("only mono PCM is supported".to_owned());
+    }
+
+    let samples: Vec<i16> = match field(14) {
+        8 => pcm.iter().map(|&s| (s as i16 - 128) << 8).collect(),
+        16 => pcm.chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]])).collect(),
+        bits => return Err(format!("unsupported sample size: {} bits", bits)),
+    };
+    if samples.is_empty() {
+        return Err("no samples".to_owned());
+    }
+
+    let length = samples.len() as u64 * SAMPLE_RATE as u64 / rate as u64;
+    Ok((0..length).map(|i| samples[(i * rate as u64 / SAMPLE_RATE as u64) as usize]).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LEVEL: i16 = 400;
+
+    /// A mixer whose sounds are `len` samples at a constant level, with the UFO looping.
+    fn mixer(len: usize) -> Mixer {
+        let voices = (0..9)
+            .map(|i| Voice { samples: vec![LEVEL; len], position: 0, playing: false, looping: i == 0 })
+            .collect();
+        Mixer { voices }
+    }
+
+    fn timed(cycle: u64, event: EmulatorEvent) -> TimedEvent {
+        TimedEvent { cycle, event }
+    }
+
+    /// Index of the first and one past the last non-silent sample.
+    fn sounding(samples: &[i16]) -> (usize, usize) {
+        let start = samples.iter().position(|&s| s != 0).unwrap();
+        let end = samples.iter().rposition(|&s| s != 0).unwrap() + 1;
+        (start, end)
+    }
+
+    #[test]
+    fn test_events_at_their_cycle() {
+        let mut mixer = mixer(100);
+        let frame_start = 10 * CLOCK_SPEED as u64;
+        let events = [
+            timed(frame_start + 2_000, EmulatorEvent::PlaySound(Sound::Shoot)),
+            timed(frame_start + 4_000, EmulatorEvent::StopSound(Sound::Shoot)),
+        ];
+
+        let out = mixer.mix_frame(&events, frame_start, frame_start + 20_000);
+        assert_eq!(out.len(), 441);
+        // 2000 cycles at 2 MHz is 44.1 samples in, and one-shot sounds ignore StopSound
+        assert_eq!(sounding(&out), (44, 144));
+        assert!(out[44..144].iter().all(|&s| s == (LEVEL as f32 * VOLUME) as i16));
+    }
+
+    #[test]
+    fn test_looping_voice() {
+        let mut mixer = mixer(50);
+        let out = mixer.mix_frame(&[timed(0, EmulatorEvent::PlaySound(Sound::UFO))], 0, 20_000);
+        assert_eq!(sounding(&out), (0, 441));
+
+        // The loop carries over into the next frame until StopSound
+        let out = mixer.mix_frame(&[timed(30_000, EmulatorEvent::StopSound(Sound::UFO))], 20_000, 40_000);
+        assert_eq!(sounding(&out), (0, 220));
+        assert!(!mixer.voices[0].playing);
+    }
+
+    #[test]
+    fn test_overlapping_voices_and_late_events() {
+        let mut mixer = mixer(1000);
+        let events = [
+            timed(0, EmulatorEvent::PlaySound(Sound::Bomp1)),
+            timed(10_000, EmulatorEvent::PlaySound(Sound::Bomp2)),
+            // Raised after the end of the frame, so applied at its last sample
+            timed(50_000, EmulatorEvent::PlaySound(Sound::UFOExplode)),
+        ];
+
+        let out = mixer.mix_frame(&events, 0, 20_000);
+        let level = (LEVEL as f32 * VOLUME) as i16;
+        assert_eq!(out[219], level);
+        assert_eq!(out[220], 2 * level);
+        assert!(mixer.voices[8].playing);
+        assert_eq!(mixer.voices[8].position, 0);
+    }
+}
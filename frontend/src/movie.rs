@@ -0,0 +1,89 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use core::{Button, Emulator, Movie, MoviePlayer, MovieRecorder};
+
+pub const MOVIE_DIR: &str = "movies";
+
+pub fn default_path() -> PathBuf {
+    PathBuf::from(MOVIE_DIR).join("recording.movie")
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum MovieState {
+    #[default]
+    Idle,
+    Recording(MovieRecorder, PathBuf),
+    Playing(MoviePlayer),
+}
+
+impl MovieState {
+    pub fn is_idle(&self) -> bool {
+        matches!(self, Self::Idle)
+    }
+
+    pub fn record(emulator: &mut Emulator, path: PathBuf, power_on: bool) -> Self {
+        Self::Recording(MovieRecorder::new(emulator, power_on), path)
+    }
+
+    pub fn play(emulator: &mut Emulator, path: &Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let movie = Movie::from_bytes(&data).map_err(|e| e.to_string())?;
+        let player = MoviePlayer::new(movie, emulator).map_err(|e| e.to_string())?;
+        Ok(Self::Playing(player))
+    }
+
+    /// Forwards a button from the user to the emulator, unless a movie is
+    /// being played back.
+    pub fn button(&mut self, emulator: &mut Emulator, frame: u64, button: Button, pressed: bool) {
+        match self {
+            Self::Playing(_) => return,
+            Self::Recording(recorder, _) => recorder.record_input(frame, button, pressed),
+            Self::Idle => {}
+        }
+
+        if pressed {
+            emulator.button_press(button);
+        } else {
+            emulator.button_release(button);
+        }
+    }
+
+    pub fn start_frame(&mut self, emulator: &mut Emulator, frame: u64) {
+        if let Self::Playing(player) = self {
+            player.start_frame(frame, emulator);
+        }
+    }
+
+    /// Returns `true` when playback has reached the end of the movie.
+    pub fn end_frame(&mut self, emulator: &Emulator, frame: u64) -> Result<bool, String> {
+        match self {
+            Self::Recording(recorder, _) => recorder.end_frame(frame, emulator),
+            Self::Playing(player) => {
+                let result = player.end_frame(frame, emulator).map_err(|e| e.to_string());
+                if result.is_err() || player.is_finished(frame + 1) {
+                    *self = Self::Idle;
+                    return result.map(|_| true);
+                }
+            }
+            Self::Idle => {}
+        }
+
+        Ok(false)
+    }
+
+    /// Stops recording or playback, writing out the movie if one was being
+    /// recorded.
+    pub fn stop(&mut self) -> Result<Option<PathBuf>, String> {
+        match std::mem::take(self) {
+            Self::Recording(recorder, path) => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                fs::write(&path, recorder.finish().to_bytes()).map_err(|e| e.to_string())?;
+                Ok(Some(path))
+            }
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::path::Path;
+
+use core::video::Overlay;
+
+pub const OVERLAY_DIR: &str = "overlays";
+
+/// Returns the built-in overlays followed by any `.overlay` definitions, `.ppm` masks or
+/// `.prom` color PROMs found in `OVERLAY_DIR`.
+pub fn load_overlays() -> Result<Vec<Overlay>, String> {
+    let mut overlays = Overlay::builtin();
+
+    let Ok(entries) = fs::read_dir(OVERLAY_DIR) else {
+        return Ok(overlays);
+    };
+
+    let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+
+    for path in paths {
+        if let Some(overlay) = load_file(&path)? {
+            overlays.push(overlay);
+        }
+    }
+
+    Ok(overlays)
+}
+
+/// Returns the index of the overlay with the given name, or loads it from a file and
+/// appends it to `overlays`.
+pub fn select(overlays: &mut Vec<Overlay>, name_or_path: &str) -> Result<usize, String> {
+    if let Some(index) = overlays.iter().position(|overlay| overlay.name().eq_ignore_ascii_case(name_or_path)) {
+        return Ok(index);
+    }
+
+    let path = Path::new(name_or_path);
+    if !path.is_file() {
+        let names: Vec<&str> = overlays.iter().map(Overlay::name).collect();
+        return Err(format!("unknown overlay `{}`, expected a file or one of: {}", name_or_path, names.join(", ")));
+    }
+
+    let overlay = load_file(path)?.ok_or_else(|| format!("{}: overlays must be .overlay, .ppm or .prom files", path.display()))?;
+    overlays.push(overlay);
+    Ok(overlays.len() - 1)
+}
+
+/// Loads an overlay definition, mask or color PROM, or returns `None` for other file types.
+fn load_file(path: &Path) -> Result<Option<Overlay>, String> {
+    let overlay = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("overlay") => fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Overlay::parse(&text).map_err(|e| e.to_string())),
+        Some("ppm") => fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| Overlay::from_mask(&file_stem(path), &data).map_err(|e| e.to_string())),
+        Some("prom") => fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| Overlay::from_color_prom(&file_stem(path), &data).map_err(|e| e.to_string())),
+        _ => return Ok(None),
+    };
+
+    overlay.map(Some).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
@@ -0,0 +1,83 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use core::video::{self, Overlay, PixelFormat, WIDTH, HEIGHT};
+use core::FPS;
+use crate::mixer::SAMPLE_RATE;
+
+pub const RECORDING_DIR: &str = "recordings";
+
+/// Records the emulated frames to a Y4M video and the mixed audio to a 16-bit WAV file.
+pub struct Recorder {
+    video: BufWriter<File>,
+    audio: BufWriter<File>,
+    audio_bytes: u32,
+    pixels: Vec<u8>,
+    path: PathBuf,
+}
+
+impl Recorder {
+    /// Starts recording to the first unused `recordings/recordingNNN.{y4m,wav}` pair.
+    pub fn start() -> Result<Self, String> {
+        fs::create_dir_all(RECORDING_DIR).map_err(|e| e.to_string())?;
+
+        let path = crate::unused_path(RECORDING_DIR, "recording", &["y4m", "wav"]);
+        let create = |extension| {
+            let path = path.with_extension(extension);
+            File::create(&path).map(BufWriter::new).map_err(|e| format!("could not create {}: {}", path.display(), e))
+        };
+
+        let mut recorder = Self { video: create("y4m")?, audio: create("wav")?, audio_bytes: 0, pixels: vec![0; PixelFormat::Rgb24.buffer_size()], path };
+        writeln!(recorder.video, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", WIDTH, HEIGHT, FPS).map_err(|e| e.to_string())?;
+        recorder.write_wav_header().map_err(|e| e.to_string())?;
+
+        Ok(recorder)
+    }
+
+    pub fn push_frame(&mut self, video_ram: &[u8], overlay: &Overlay, samples: &[i16]) -> Result<(), String> {
+        video::render(video_ram, overlay, PixelFormat::Rgb24, &mut self.pixels);
+
+        let mut planes = vec![0; WIDTH * HEIGHT * 3];
+        let (y_plane, chroma) = planes.split_at_mut(WIDTH * HEIGHT);
+        let (u_plane, v_plane) = chroma.split_at_mut(WIDTH * HEIGHT);
+        for (i, rgb) in self.pixels.chunks_exact(3).enumerate() {
+            let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(|c| c as i32);
+            y_plane[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+            u_plane[i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            v_plane[i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+
+        self.video.write_all(b"FRAME\n").and_then(|_| self.video.write_all(&planes)).map_err(|e| e.to_string())?;
+
+        let audio: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        self.audio.write_all(&audio).map_err(|e| e.to_string())?;
+        self.audio_bytes += audio.len() as u32;
+
+        Ok(())
+    }
+
+    /// Finalizes both files and returns their path without extension.
+    pub fn finish(mut self) -> Result<PathBuf, String> {
+        self.video.flush().map_err(|e| e.to_string())?;
+        self.audio.seek(SeekFrom::Start(0))
+            .and_then(|_| self.write_wav_header())
+            .and_then(|_| self.audio.flush())
+            .map_err(|e| e.to_string())?;
+
+        Ok(self.path)
+    }
+
+    fn write_wav_header(&mut self) -> std::io::Result<()> {
+        let header = [
+            &b"RIFF"[..], &(36 + self.audio_bytes).to_le_bytes(), b"WAVE",
+            b"fmt ", &16u32.to_le_bytes(),
+            &1u16.to_le_bytes(), &1u16.to_le_bytes(),                       // PCM, mono
+            &SAMPLE_RATE.to_le_bytes(), &(SAMPLE_RATE * 2).to_le_bytes(),   // sample rate, byte rate
+            &2u16.to_le_bytes(), &16u16.to_le_bytes(),                      // block align, bits per sample
+            b"data", &self.audio_bytes.to_le_bytes(),
+        ];
+
+        self.audio.write_all(&header.concat())
+    }
+}
@@ -0,0 +1,22 @@
+use std::fs;
+use std::path::PathBuf;
+
+use core::Emulator;
+
+pub const SAVE_DIR: &str = "saves";
+pub const SLOTS: u8 = 10;
+
+pub fn slot_path(slot: u8) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(format!("slot{}.state", slot))
+}
+
+pub fn save(emulator: &Emulator, slot: u8) -> Result<(), String> {
+    fs::create_dir_all(SAVE_DIR).map_err(|e| e.to_string())?;
+    fs::write(slot_path(slot), emulator.save_state()).map_err(|e| e.to_string())
+}
+
+pub fn load(emulator: &mut Emulator, slot: u8) -> Result<(), String> {
+    let path = slot_path(slot);
+    let data = fs::read(&path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    emulator.load_state(&data).map_err(|e| e.to_string())
+}
@@ -0,0 +1,21 @@
+use std::fs;
+use std::path::PathBuf;
+
+use core::Emulator;
+use core::video::{self, Overlay, Rgb};
+
+pub const SCREENSHOT_DIR: &str = "screenshots";
+pub const SCALE: usize = 3;
+
+/// Writes the screen to the first unused `screenshots/screenshotNNN.png` file.
+pub fn save(emulator: &Emulator, overlay: Option<&Overlay>, scale: usize) -> Result<PathBuf, String> {
+    fs::create_dir_all(SCREENSHOT_DIR).map_err(|e| e.to_string())?;
+
+    let path = crate::unused_path(SCREENSHOT_DIR, "screenshot", &["png"]).with_extension("png");
+
+    let monochrome = Overlay::monochrome("None", Rgb::WHITE);
+    let data = video::encode_png(emulator.video_ram(), overlay.unwrap_or(&monochrome), scale);
+    fs::write(&path, data).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+
+    Ok(path)
+}
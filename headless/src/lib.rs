@@ -0,0 +1,125 @@
+use std::fmt;
+use core::{Button, Emulator, MovieInput};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub frame: u64,
+    pub video_hash: u32,
+    pub ram_hash: u32,
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:08x} {:08x}", self.frame, self.video_hash, self.ram_hash)
+    }
+}
+
+/// Parses an input script made of `<frame> press|release <button>` lines.
+/// Blank lines and lines starting with `#` are ignored.
+pub fn parse_script(text: &str) -> Result<Vec<MovieInput>, String> {
+    let mut inputs = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = |msg: &str| format!("line {}: {}", i + 1, msg);
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let [frame, action, button] = parts[..] else {
+            return Err(error("expected `<frame> press|release <button>`"));
+        };
+
+        let frame = frame.parse().map_err(|_| error("invalid frame number"))?;
+        let pressed = match action {
+            "press" => true,
+            "release" => false,
+            _ => return Err(error("action must be `press` or `release`")),
+        };
+        let button = Button::from_name(button).ok_or_else(|| error(&format!("unknown button `{}`", button)))?;
+
+        inputs.push(MovieInput { frame, button, pressed });
+    }
+
+    inputs.sort_by_key(|input| input.frame);
+    Ok(inputs)
+}
+
+/// Parses checkpoints as written by `Checkpoint`'s `Display` impl, one per line.
+pub fn parse_checkpoints(text: &str) -> Result<Vec<Checkpoint>, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let [frame, video_hash, ram_hash] = parts[..] else {
+                return Err(format!("invalid checkpoint `{}`", line));
+            };
+
+            Ok(Checkpoint {
+                frame: frame.parse().map_err(|_| format!("invalid frame in `{}`", line))?,
+                video_hash: u32::from_str_radix(video_hash, 16).map_err(|_| format!("invalid hash in `{}`", line))?,
+                ram_hash: u32::from_str_radix(ram_hash, 16).map_err(|_| format!("invalid hash in `{}`", line))?,
+            })
+        })
+        .collect()
+}
+
+/// Runs `frames` frames, applying `inputs` at the start of their frame, and returns
+/// the hashes taken after each frame listed in `checkpoints`.
+pub fn run(emulator: &mut Emulator, frames: u64, inputs: &[MovieInput], checkpoints: &[u64]) -> Result<Vec<Checkpoint>, String> {
+    run_with(emulator, frames, inputs, checkpoints, |_, _| Ok(()))
+}
+
+/// Same as `run`, calling `on_frame` with the number of completed frames after each one.
+pub fn run_with<F>(emulator: &mut Emulator, frames: u64, inputs: &[MovieInput], checkpoints: &[u64], mut on_frame: F) -> Result<Vec<Checkpoint>, String>
+where
+    F: FnMut(u64, &Emulator) -> Result<(), String>,
+{
+    let mut inputs = inputs.iter().peekable();
+    let mut results = Vec::new();
+
+    for frame in 0..frames {
+        while let Some(input) = inputs.next_if(|input| input.frame <= frame) {
+            match input.pressed {
+                true => emulator.button_press(input.button),
+                false => emulator.button_release(input.button),
+            }
+        }
+
+        emulator.run_frame().map_err(|e| format!("frame {}: {}", frame, e))?;
+        on_frame(frame + 1, emulator)?;
+
+        if checkpoints.contains(&(frame + 1)) {
+            results.push(Checkpoint { frame: frame + 1, video_hash: emulator.video_hash(), ram_hash: emulator.ram_hash() });
+        }
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let inputs = parse_script("# insert a coin\n30 release coin\n\n10 press coin\n").unwrap();
+        assert_eq!(inputs, [
+            MovieInput { frame: 10, button: Button::Coin, pressed: true },
+            MovieInput { frame: 30, button: Button::Coin, pressed: false },
+        ]);
+
+        assert_eq!(parse_script("10 press").unwrap_err(), "line 1: expected `<frame> press|release <button>`");
+        assert_eq!(parse_script("\n10 hold coin").unwrap_err(), "line 2: action must be `press` or `release`");
+        assert_eq!(parse_script("10 press fire").unwrap_err(), "line 1: unknown button `fire`");
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let checkpoint = Checkpoint { frame: 60, video_hash: 0x0123ABCD, ram_hash: 0xDEADBEEF };
+        assert_eq!(checkpoint.to_string(), "60 0123abcd deadbeef");
+        assert_eq!(parse_checkpoints(&checkpoint.to_string()).unwrap(), [checkpoint]);
+    }
+}
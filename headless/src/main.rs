@@ -0,0 +1,228 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use core::{rom, DipSwitches, Emulator, Movie, MoviePlayer};
+use core::machine::{Coloring, Machine};
+use core::video::{self, GifRecorder, Overlay, Rgb};
+use headless::Checkpoint;
+
+const USAGE: &str = "usage: headless <rom> [--machine <name>] [--frames <n>] [--state <file>] [--script <file> | --play <file>] [--at <frame,...>]
+                [--output <file>] [--check <file>] [--screenshot <file.png>] [--scale <n>] [--overlay <name|file> | --no-overlay]
+                [--gif <file.gif>] [--gif-start <frame>]";
+
+struct Options {
+    rom: String,
+    machine: Option<&'static Machine>,
+    frames: Option<u64>,
+    state: Option<String>,
+    script: Option<String>,
+    play: Option<String>,
+    at: Vec<u64>,
+    output: Option<String>,
+    check: Option<String>,
+    screenshot: Option<String>,
+    scale: usize,
+    overlay: Option<String>,
+    no_overlay: bool,
+    gif: Option<String>,
+    gif_start: u64,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match parse_args(&args).and_then(|options| run(&options)) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options { rom: String::new(), machine: None, frames: None, state: None, script: None, play: None, at: Vec::new(), output: None, check: None, screenshot: None, scale: 1, overlay: None, no_overlay: false, gif: None, gif_start: 0 };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(|| format!("{} expects a value\n{}", arg, USAGE));
+        match arg.as_str() {
+            "--machine" => {
+                let name = value()?;
+                options.machine = Some(Machine::from_name(&name).ok_or_else(|| format!("unknown machine `{}`", name))?);
+            }
+            "--frames" => options.frames = Some(value()?.parse().map_err(|_| "--frames expects a number".to_owned())?),
+            "--state" => options.state = Some(value()?),
+            "--script" => options.script = Some(value()?),
+            "--play" => options.play = Some(value()?),
+            "--at" => {
+                for frame in value()?.split(',') {
+                    options.at.push(frame.trim().parse().map_err(|_| format!("invalid frame `{}` in --at", frame))?);
+                }
+            }
+            "--output" => options.output = Some(value()?),
+            "--check" => options.check = Some(value()?),
+            "--screenshot" => options.screenshot = Some(value()?),
+            "--scale" => options.scale = value()?.parse().ok().filter(|&scale| scale > 0).ok_or("--scale expects a positive number")?,
+            "--overlay" => options.overlay = Some(value()?),
+            "--no-overlay" => options.no_overlay = true,
+            "--gif" => options.gif = Some(value()?),
+            "--gif-start" => options.gif_start = value()?.parse().map_err(|_| "--gif-start expects a frame number".to_owned())?,
+            "-h" | "--help" => return Err(USAGE.to_owned()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`\n{}", arg, USAGE)),
+            _ if options.rom.is_empty() => options.rom = arg.clone(),
+            _ => return Err(format!("unexpected argument `{}`\n{}", arg, USAGE)),
+        }
+    }
+
+    if options.rom.is_empty() {
+        return Err(USAGE.to_owned());
+    }
+    if options.script.is_some() && options.play.is_some() {
+        return Err("--script and --play can't be combined".to_owned());
+    }
+
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<bool, String> {
+    let read = |path: &str| fs::read(path).map_err(|e| format!("could not read {}: {}", path, e));
+
+    let program = rom::load(Path::new(&options.rom)).map_err(|e| format!("could not load ROM from {}: {}", options.rom, e))?;
+    let mut emulator = match options.machine {
+        Some(machine) => Emulator::with_machine(machine, &program, DipSwitches::default()),
+        None => Emulator::new(&program),
+    }
+    .map_err(|e| e.to_string())?;
+
+    if let Some(path) = &options.state {
+        emulator.load_state(&read(path)?).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    // A movie brings its own inputs, replayed through the same path as a script
+    let (mut player, mut movie_length) = (None, None);
+    let inputs = match (&options.script, &options.play) {
+        (Some(path), _) => headless::parse_script(&String::from_utf8_lossy(&read(path)?)).map_err(|e| format!("{}: {}", path, e))?,
+        (None, Some(path)) => {
+            let movie = Movie::from_bytes(&read(path)?).map_err(|e| format!("{}: {}", path, e))?;
+            let inputs = movie.inputs().to_vec();
+            movie_length = Some(movie.length());
+            player = Some(MoviePlayer::new(movie, &mut emulator).map_err(|e| format!("{}: {}", path, e))?);
+            inputs
+        }
+        (None, None) => Vec::new(),
+    };
+    let expected = match &options.check {
+        Some(path) => Some(headless::parse_checkpoints(&String::from_utf8_lossy(&read(path)?)).map_err(|e| format!("{}: {}", path, e))?),
+        None => None,
+    };
+
+    let mut at = options.at.clone();
+    if let Some(expected) = &expected {
+        at.extend(expected.iter().map(|checkpoint| checkpoint.frame));
+    }
+    let frames = options.frames.or_else(|| at.iter().max().copied()).or(movie_length).ok_or("--frames is required without --at, --check or --play")?;
+    if at.is_empty() {
+        at.push(frames);
+    }
+
+    let overlay = match (&options.overlay, options.no_overlay) {
+        (_, true) => Overlay::monochrome("None", Rgb::WHITE),
+        (Some(name), false) => select_overlay(name)?,
+        (None, false) => machine_overlay(emulator.machine(), &program),
+    };
+    let mut gif = match &options.gif {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("could not create {}: {}", path, e))?;
+            Some(GifRecorder::new(BufWriter::new(file), &overlay).map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
+
+    let results = headless::run_with(&mut emulator, frames, &inputs, &at, |frame, emulator| {
+        if let Some(player) = &mut player {
+            player.end_frame(frame - 1, emulator).map_err(|e| e.to_string())?;
+        }
+
+        match &mut gif {
+            Some(gif) if frame > options.gif_start => gif.push_frame(emulator.video_ram()).map_err(|e| e.to_string()),
+            _ => Ok(()),
+        }
+    })?;
+
+    if let Some(gif) = gif {
+        gif.finish().and_then(|mut writer| writer.flush()).map_err(|e| format!("could not write GIF: {}", e))?;
+    }
+
+    let output: String = results.iter().map(|checkpoint| format!("{}\n", checkpoint)).collect();
+    match &options.output {
+        Some(path) => fs::write(path, output).map_err(|e| format!("could not write {}: {}", path, e))?,
+        None => print!("{}", output),
+    }
+
+    if let Some(path) = &options.screenshot {
+        let data = video::encode_png(emulator.video_ram(), &overlay, options.scale);
+        fs::write(path, data).map_err(|e| format!("could not write {}: {}", path, e))?;
+    }
+
+    Ok(expected.is_none_or(|expected| check(&expected, &results)))
+}
+
+/// The overlay the frontend would start with: the identified set's, else the machine's own.
+fn machine_overlay(machine: &'static Machine, program: &[u8]) -> Overlay {
+    let name = match (rom::identify(program).filter(|set| set.machine == machine), machine.coloring) {
+        (Some(set), _) => set.overlay,
+        (None, Coloring::Overlay(name)) => name,
+        (None, Coloring::ColorProm) => {
+            eprintln!("{} colors come from its color PROM, pass it with --overlay <file.prom>", machine.title);
+            return Overlay::monochrome("None", Rgb::WHITE);
+        }
+    };
+
+    Overlay::builtin().into_iter().find(|overlay| overlay.name() == name).unwrap_or_default()
+}
+
+/// A built-in overlay by name, or one loaded from a `.overlay`, `.ppm` or `.prom` file.
+fn select_overlay(name_or_path: &str) -> Result<Overlay, String> {
+    let builtin = Overlay::builtin();
+    if let Some(overlay) = builtin.iter().find(|overlay| overlay.name().eq_ignore_ascii_case(name_or_path)) {
+        return Ok(overlay.clone());
+    }
+
+    let path = Path::new(name_or_path);
+    let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let read = || fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e));
+    let overlay = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("overlay") => Overlay::parse(&String::from_utf8_lossy(&read()?)),
+        Some("ppm") => Overlay::from_mask(&name, &read()?),
+        Some("prom") => Overlay::from_color_prom(&name, &read()?),
+        _ => {
+            let names: Vec<&str> = builtin.iter().map(Overlay::name).collect();
+            return Err(format!("unknown overlay `{}`, expected a .overlay, .ppm or .prom file or one of: {}", name_or_path, names.join(", ")));
+        }
+    };
+
+    overlay.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn check(expected: &[Checkpoint], results: &[Checkpoint]) -> bool {
+    let mut ok = true;
+
+    for checkpoint in expected {
+        match results.iter().find(|result| result.frame == checkpoint.frame) {
+            Some(result) if result == checkpoint => {}
+            Some(result) => {
+                eprintln!("mismatch at frame {}: expected {}, got {}", checkpoint.frame, checkpoint, result);
+                ok = false;
+            }
+            None => {
+                eprintln!("frame {} was not reached", checkpoint.frame);
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
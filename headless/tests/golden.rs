@@ -0,0 +1,19 @@
+use std::fs;
+use std::path::Path;
+use core::Emulator;
+
+#[test]
+fn test_gameplay_golden() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let program = fs::read(root.join("../frontend/assets/invaders")).unwrap();
+    let script = fs::read_to_string(root.join("tests/data/gameplay.script")).unwrap();
+    let golden = fs::read_to_string(root.join("tests/data/gameplay.golden")).unwrap();
+
+    let inputs = headless::parse_script(&script).unwrap();
+    let expected = headless::parse_checkpoints(&golden).unwrap();
+    let frames: Vec<u64> = expected.iter().map(|checkpoint| checkpoint.frame).collect();
+
+    let mut emulator = Emulator::new(&program).unwrap();
+    let results = headless::run(&mut emulator, *frames.last().unwrap(), &inputs, &frames).unwrap();
+    assert_eq!(results, expected);
+}